@@ -0,0 +1,99 @@
+use chrono::Local;
+use sea_orm::DbErr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// The outages of the endpoints during a resilient run. An outage lasts
+/// from the first transaction that lost its connection to the first commit
+/// after it.
+pub struct Availability {
+    started: Instant,
+    endpoints: Mutex<Vec<EndpointState>>,
+}
+
+#[derive(Default)]
+struct EndpointState {
+    down_since: Option<Instant>,
+    last_commit: Option<Instant>,
+    outages: Vec<Duration>,
+    committed: u64,
+    failed: u64,
+}
+
+impl Availability {
+    pub fn new(endpoints: usize) -> Self {
+        let mut states = Vec::new();
+        states.resize_with(endpoints, EndpointState::default);
+        Self {
+            started: Instant::now(),
+            endpoints: Mutex::new(states),
+        }
+    }
+
+    /// Records a transaction begun at `attempted` that lost its connection.
+    /// The outage starts with the attempt, which may have waited for the pool
+    /// as long as the acquire timeout before failing, but not before the last
+    /// commit of the endpoint, which proves it was still up.
+    pub fn failed(&self, endpoint: usize, err: &DbErr, attempted: Instant) {
+        let mut endpoints = self.endpoints.lock().expect("availability poisoned");
+        let state = &mut endpoints[endpoint];
+        state.failed += 1;
+        let since = state
+            .last_commit
+            .map_or(attempted, |last_commit| last_commit.max(attempted));
+        if let Some(down_since) = &mut state.down_since {
+            *down_since = (*down_since).min(since);
+        } else {
+            state.down_since = Some(since);
+            println!(
+                "{} [OUTAGE] Endpoint #{} lost its connection: {}",
+                Local::now(),
+                endpoint + 1,
+                err
+            );
+        }
+    }
+
+    pub fn committed(&self, endpoint: usize) {
+        let mut endpoints = self.endpoints.lock().expect("availability poisoned");
+        let state = &mut endpoints[endpoint];
+        state.committed += 1;
+        state.last_commit = Some(Instant::now());
+        if let Some(down_since) = state.down_since.take() {
+            let outage = down_since.elapsed();
+            state.outages.push(outage);
+            println!(
+                "{} [RECOVERED] Endpoint #{} committed again after {:.3}s",
+                Local::now(),
+                endpoint + 1,
+                outage.as_secs_f64()
+            );
+        }
+    }
+
+    /// Prints the share of the run each endpoint was up, counting an outage
+    /// still going on until now.
+    pub fn report(&self) {
+        let elapsed = self.started.elapsed();
+        let endpoints = self.endpoints.lock().expect("availability poisoned");
+        println!("Availability over {:.1}s:", elapsed.as_secs_f64());
+        for (endpoint, state) in endpoints.iter().enumerate() {
+            let ongoing = state.down_since.map(|down_since| down_since.elapsed());
+            let down = state.outages.iter().sum::<Duration>() + ongoing.unwrap_or_default();
+            let longest = state.outages.iter().chain(&ongoing).max().copied();
+            let attempts = (state.committed + state.failed).max(1);
+            println!(
+                "Endpoint #{}: up {:.3}% of the time, {:.3}% of the attempts committed, {} outages{}{}",
+                endpoint + 1,
+                100.0 * (1.0 - down.as_secs_f64() / elapsed.as_secs_f64().max(f64::EPSILON)),
+                100.0 * state.committed as f64 / attempts as f64,
+                state.outages.len() + ongoing.is_some() as usize,
+                longest.map_or(String::new(), |longest| format!(
+                    ", longest {:.3}s",
+                    longest.as_secs_f64()
+                )),
+                if ongoing.is_some() { ", still down" } else { "" }
+            );
+        }
+    }
+}
@@ -0,0 +1,191 @@
+use crate::entity::{
+    self, archive, commodity, consumer, evaluation, inventory,
+    key::Id,
+    meta, order,
+    types::{self, Timestamp},
+};
+use crate::migration::SCHEMA_VERSION;
+use anyhow::{bail, Context, Result};
+use sea_orm::{
+    sea_query::{Alias, Expr, Query},
+    ColumnTrait, ConnectionTrait, DatabaseConnection, EntityName, EntityTrait, FromQueryResult,
+    PaginatorTrait, QueryFilter, QuerySelect,
+};
+use std::collections::HashMap;
+
+#[derive(Debug, FromQueryResult)]
+struct SoldNumber {
+    commodity_id: Id,
+    sold_number: i64,
+}
+
+pub async fn execute(db: &DatabaseConnection) -> Result<()> {
+    let version = meta::find_version(db)
+        .await
+        .context("Failed to read meta")?
+        .context("No meta found, please prepare first")?;
+    if !version.is_compatible() {
+        bail!(
+            "Schema version {} is incompatible with this build, which expects {}",
+            version.schema_version,
+            SCHEMA_VERSION
+        );
+    }
+    let meta = meta::find(db)
+        .await
+        .context("Failed to read meta")?
+        .context("No meta found, please prepare first")?;
+    types::set_column_types(
+        meta.column_types()
+            .with_context(|| format!("Unknown column types {} in meta", meta.column_types))?,
+    );
+    println!(
+        "Prepared by tiny_orders {} at {} UTC, schema version {}, scale factor {}, seed {}, key strategy {}, column types {}",
+        meta.tool_version,
+        meta.prepared_at,
+        meta.schema_version,
+        meta.scale_factor,
+        meta.seed(),
+        meta.key_strategy,
+        meta.column_types
+    );
+    let mut passed = true;
+    passed &= check_count(db, commodity::Entity, meta.commodity_count, None).await?;
+    passed &= check_count(db, inventory::Entity, meta.commodity_count, None).await?;
+    passed &= check_count(db, consumer::Entity, meta.consumer_count, None).await?;
+    passed &= check_count(
+        db,
+        order::Entity,
+        meta.order_count,
+        Some(meta.purged_order_count),
+    )
+    .await?;
+    passed &= check_count(
+        db,
+        evaluation::Entity,
+        meta.evaluation_count,
+        Some(meta.purged_evaluation_count),
+    )
+    .await?;
+    passed &= check_stock(db, &meta).await?;
+    if !passed {
+        bail!("Check failed");
+    }
+    println!("Check passed.");
+    Ok(())
+}
+
+/// Compares the row count of `entity` with the prepared count. Tables that
+/// `run` appends to and archives from only need to hold at least the
+/// prepared rows, counting the ones moved to their archive and the `purged`
+/// ones deleted for good.
+async fn check_count<E>(
+    db: &DatabaseConnection,
+    entity: E,
+    expected: i64,
+    purged: Option<i64>,
+) -> Result<bool>
+where
+    E: EntityTrait,
+    E::Model: Sync,
+{
+    let count = E::find().count(db).await? as i64;
+    let archived = archive::archived_rows(db, entity).await?.unwrap_or(0) as i64;
+    let (passed, removed) = match purged {
+        Some(purged) => (
+            count + archived + purged >= expected,
+            format!(", {} archived and {} deleted", archived, purged),
+        ),
+        None => (count == expected, String::new()),
+    };
+    println!(
+        "[{}] {} has {} rows{}, prepared {}",
+        if passed { "OK" } else { "FAIL" },
+        entity.table_name(),
+        count,
+        removed,
+        expected
+    );
+    Ok(passed)
+}
+
+/// Every unit sold since prepare must have been taken from the inventory,
+/// so the remaining stock plus the sold units adds up to the initial stock.
+/// The units of the orders deleted for good are only known in total, they
+/// have to make up exactly for the stock the remaining orders don't explain.
+async fn check_stock(db: &DatabaseConnection, meta: &meta::Model) -> Result<bool> {
+    let prepared_at = Timestamp::from_utc(meta.prepared_at);
+    let backend = db.get_database_backend();
+    let mut sold: HashMap<Id, i64> = order::Entity::find()
+        .select_only()
+        .column(order::Column::CommodityId)
+        .column_as(Expr::cust(order::sold_number_sum(backend)), "sold_number")
+        .filter(order::Column::CreatedAt.gte(prepared_at))
+        .group_by(order::Column::CommodityId)
+        .into_model::<SoldNumber>()
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|row| (row.commodity_id, row.sold_number))
+        .collect();
+    let archive_table = archive::archive_table(order::Entity.table_name());
+    if entity::table_exists(db, &archive_table).await? {
+        let archived_stmt = Query::select()
+            .column(order::Column::CommodityId)
+            .expr_as(
+                Expr::cust(order::sold_number_sum(backend)),
+                Alias::new("sold_number"),
+            )
+            .from(Alias::new(&archive_table))
+            .and_where(Expr::col(order::Column::CreatedAt).gte(prepared_at))
+            .group_by_col(order::Column::CommodityId)
+            .to_owned();
+        for row in SoldNumber::find_by_statement(backend.build(&archived_stmt))
+            .all(db)
+            .await?
+        {
+            *sold.entry(row.commodity_id).or_default() += row.sold_number;
+        }
+    }
+    let mut mismatched = Vec::new();
+    let mut unexplained = 0;
+    let mut pages = inventory::Entity::find().paginate(db, 10000);
+    while let Some(inventories) = pages.fetch_and_next().await? {
+        for inventory in inventories {
+            let sold_number = sold.get(&inventory.commodity_id).copied().unwrap_or(0);
+            let missing = meta.initial_stock - inventory.inventory - sold_number;
+            if missing != 0 {
+                unexplained += missing;
+                mismatched.push((inventory, sold_number, missing));
+            }
+        }
+    }
+    // the purged orders can only account for stock that is missing
+    let purged = meta.purged_sold_number;
+    if purged > 0 && unexplained == purged && mismatched.iter().all(|(.., missing)| *missing > 0) {
+        println!(
+            "[OK] {} units sold by deleted orders account for the stock of {} commodities",
+            purged,
+            mismatched.len()
+        );
+        return Ok(true);
+    }
+    for (inventory, sold_number, _) in mismatched.iter().take(10) {
+        println!(
+            "[FAIL] commodity {} has {} in stock and {} sold, expected {} in total",
+            inventory.commodity_id, inventory.inventory, sold_number, meta.initial_stock
+        );
+    }
+    if purged > 0 {
+        println!(
+            "[FAIL] {} units are missing from the stock, the deleted orders sold {}",
+            unexplained, purged
+        );
+    }
+    println!(
+        "[{}] {} commodities with inconsistent stock",
+        if mismatched.is_empty() { "OK" } else { "FAIL" },
+        mismatched.len()
+    );
+    Ok(mismatched.is_empty())
+}
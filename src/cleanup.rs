@@ -0,0 +1,33 @@
+use crate::entity;
+use anyhow::{Context, Result};
+use sea_orm::DatabaseConnection;
+
+pub async fn execute(db: &DatabaseConnection, args: &super::Args) -> Result<()> {
+    let dry_run = match args.command {
+        super::SubCommandArgs::Cleanup { dry_run } => dry_run,
+        _ => unreachable!(),
+    };
+    let tables = entity::existing_tables(db)
+        .await
+        .context("Failed to look for existing tables")?;
+    if tables.is_empty() {
+        println!("No dataset tables found.");
+        return Ok(());
+    }
+    for (table, rows) in &tables {
+        println!(
+            "{} {} ({} rows)",
+            if dry_run { "Would drop" } else { "Dropping" },
+            table,
+            rows
+        );
+    }
+    if dry_run {
+        return Ok(());
+    }
+    entity::drop_tables(db)
+        .await
+        .context("Failed to drop tables")?;
+    println!("Finished cleanup.");
+    Ok(())
+}
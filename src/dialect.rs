@@ -0,0 +1,178 @@
+use sea_orm::{ConnectionTrait, DatabaseBackend, DatabaseConnection, DbErr, Statement};
+use std::fmt;
+use std::sync::OnceLock;
+
+/// The database product behind a backend, which sea-orm only knows as
+/// MySQL, Postgres or SQLite.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Dialect {
+    MySql,
+    TiDb,
+    MariaDb,
+    Postgres,
+    CockroachDb,
+    YugabyteDb,
+    Sqlite,
+}
+
+impl fmt::Display for Dialect {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Dialect::MySql => "MySQL",
+            Dialect::TiDb => "TiDB",
+            Dialect::MariaDb => "MariaDB",
+            Dialect::Postgres => "PostgreSQL",
+            Dialect::CockroachDb => "CockroachDB",
+            Dialect::YugabyteDb => "YugabyteDB",
+            Dialect::Sqlite => "SQLite",
+        })
+    }
+}
+
+impl Dialect {
+    fn from_version(backend: DatabaseBackend, version: &str) -> Self {
+        match backend {
+            DatabaseBackend::MySql if version.contains("TiDB") => Dialect::TiDb,
+            DatabaseBackend::MySql if version.contains("MariaDB") => Dialect::MariaDb,
+            DatabaseBackend::MySql => Dialect::MySql,
+            DatabaseBackend::Postgres if version.contains("CockroachDB") => Dialect::CockroachDb,
+            DatabaseBackend::Postgres if version.contains("-YB-") => Dialect::YugabyteDb,
+            DatabaseBackend::Postgres => Dialect::Postgres,
+            DatabaseBackend::Sqlite => Dialect::Sqlite,
+        }
+    }
+
+    /// Whether the transaction failed on a conflict with a concurrent one
+    /// and may succeed if run again.
+    pub fn is_retryable(&self, err: &DbErr) -> bool {
+        let message = match err {
+            DbErr::Exec(message) | DbErr::Query(message) => message,
+            _ => return false,
+        };
+        match self {
+            Dialect::MySql | Dialect::MariaDb => {
+                // deadlock, lock wait timeout
+                matches!(mysql_error_number(message), Some(1213 | 1205))
+            }
+            Dialect::TiDb => {
+                // plus write conflict, pessimistic lock conflict, retryable
+                // transaction and schema changed during the transaction
+                matches!(
+                    mysql_error_number(message),
+                    Some(1213 | 1205 | 9007 | 8002 | 8022 | 8028)
+                )
+            }
+            // the Postgres driver only reports the message, not the SQLSTATE
+            Dialect::Postgres => {
+                message.contains("could not serialize access")
+                    || message.contains("deadlock detected")
+            }
+            Dialect::CockroachDb => message.contains("restart transaction"),
+            Dialect::YugabyteDb => {
+                message.contains("could not serialize access")
+                    || message.contains("deadlock detected")
+                    || message.contains("Restart read required")
+                    || message.contains("Try again")
+            }
+            Dialect::Sqlite => message.contains("database is locked"),
+        }
+    }
+
+    /// Whether the transaction failed because the connection to the server
+    /// broke or no connection could be had, as when a node goes down.
+    pub fn is_connection_lost(&self, err: &DbErr) -> bool {
+        let message = match err {
+            DbErr::Conn(message) | DbErr::Exec(message) | DbErr::Query(message) => message,
+            _ => return false,
+        };
+        // what sqlx reports for broken sockets and an exhausted or closed pool
+        const LOST: &[&str] = &[
+            "error communicating with database",
+            "Failed to acquire connection from pool",
+            "pool timed out",
+            "closed pool",
+        ];
+        if LOST.iter().any(|lost| message.contains(lost)) {
+            return true;
+        }
+        match self {
+            Dialect::MySql | Dialect::MariaDb | Dialect::TiDb => {
+                // server gone away, lost connection, shutdown in progress
+                matches!(mysql_error_number(message), Some(2006 | 2013 | 1053))
+            }
+            Dialect::Postgres | Dialect::CockroachDb | Dialect::YugabyteDb => {
+                message.contains("terminating connection") || message.contains("shutting down")
+            }
+            Dialect::Sqlite => false,
+        }
+    }
+
+    /// Whether creating an index failed because it already exists, which the
+    /// MySQL family can't skip with `IF NOT EXISTS`.
+    pub fn is_duplicate_index(&self, err: &DbErr) -> bool {
+        match (self, err) {
+            (Dialect::MySql | Dialect::MariaDb | Dialect::TiDb, DbErr::Exec(message)) => {
+                // duplicate key name
+                mysql_error_number(message) == Some(1061)
+            }
+            _ => false,
+        }
+    }
+
+    /// Settings the DDL session needs before creating a table.
+    pub fn create_table_settings(&self) -> &'static [&'static str] {
+        match self {
+            // keep SERIAL columns sequential instead of unique_rowid()
+            Dialect::CockroachDb => &["SET LOCAL serial_normalization = sql_sequence"],
+            _ => &[],
+        }
+    }
+}
+
+/// Extracts the error number from a MySQL error, e.g. `1213` from
+/// `error returned from database: 1213 (40001): Deadlock found ...`.
+fn mysql_error_number(message: &str) -> Option<u32> {
+    let (_, rest) = message.split_once("error returned from database: ")?;
+    let end = rest
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(rest.len());
+    rest[..end].parse().ok()
+}
+
+#[derive(Debug)]
+pub struct Server {
+    pub dialect: Dialect,
+    pub version: String,
+}
+
+static SERVER: OnceLock<Server> = OnceLock::new();
+
+/// Identifies the database by its version string. Called once after connecting.
+pub async fn detect(db: &DatabaseConnection) -> Result<&'static Server, DbErr> {
+    let backend = db.get_database_backend();
+    let query = match backend {
+        DatabaseBackend::Sqlite => "SELECT sqlite_version() AS version",
+        _ => "SELECT version() AS version",
+    };
+    let version: String = db
+        .query_one(Statement::from_string(backend, query.to_owned()))
+        .await?
+        .ok_or_else(|| DbErr::Custom("The server didn't report its version".to_owned()))?
+        .try_get("", "version")?;
+    let server = Server {
+        dialect: Dialect::from_version(backend, &version),
+        version,
+    };
+    Ok(SERVER.get_or_init(|| server))
+}
+
+/// The server found by `detect`.
+pub fn server() -> &'static Server {
+    SERVER
+        .get()
+        .expect("the database dialect is not detected yet")
+}
+
+pub fn dialect() -> Dialect {
+    server().dialect
+}
@@ -0,0 +1,205 @@
+use super::key::{self, Id};
+use super::types::{self, Timestamp};
+use super::{meta, order};
+use sea_orm::{
+    sea_query::{Alias, Expr, Query, Table},
+    ColumnTrait, Condition, ConnectionTrait, DatabaseConnection, DatabaseTransaction, DbErr,
+    EntityName, EntityTrait, FromQueryResult, Iterable, PaginatorTrait, PrimaryKeyToColumn,
+    QueryFilter, QueryOrder, QuerySelect, Schema,
+};
+
+/// What the archival service does with rows past the retention window.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArchiveMode {
+    /// Delete the rows
+    Delete,
+    /// Copy the rows to `<table>_archive`, then delete them
+    Move,
+}
+
+/// The table `move` copies the rows of `table` to.
+pub fn archive_table(table: &str) -> String {
+    format!("{}_archive", table)
+}
+
+/// Creates the archive table of `entity` with the columns of the table,
+/// but none of its keys, indexes or partitions.
+pub async fn create_archive_table<E>(db: &DatabaseConnection, entity: E) -> Result<(), DbErr>
+where
+    E: EntityTrait,
+{
+    // built from the entity, TiDB has no CREATE TABLE ... AS SELECT
+    let backend = db.get_database_backend();
+    let mut create_stmt = Table::create();
+    create_stmt
+        .table(Alias::new(&archive_table(entity.table_name())))
+        .if_not_exists();
+    for column in Schema::new(backend)
+        .create_table_from_entity(entity)
+        .get_columns()
+    {
+        // typed like a key referencing the table, without primary key or default
+        let column = key::strategy().column_def(column, true, true);
+        create_stmt.col(&mut types::column_types().column_def(&column, backend));
+    }
+    db.execute(backend.build(&create_stmt)).await?;
+    Ok(())
+}
+
+/// Drops the archive table of `entity`, if any.
+pub async fn drop_archive_table<E>(db: &DatabaseConnection, entity: E) -> Result<(), DbErr>
+where
+    E: EntityTrait,
+{
+    let mut drop_stmt = Table::drop();
+    drop_stmt
+        .if_exists()
+        .table(Alias::new(&archive_table(entity.table_name())));
+    db.execute(db.get_database_backend().build(&drop_stmt))
+        .await?;
+    Ok(())
+}
+
+/// Counts the rows in the archive table of `entity`, `None` if it doesn't exist.
+pub async fn archived_rows<E>(db: &DatabaseConnection, entity: E) -> Result<Option<u64>, DbErr>
+where
+    E: EntityTrait,
+{
+    super::count_rows(db, &archive_table(entity.table_name())).await
+}
+
+#[derive(FromQueryResult)]
+struct SoldNumber {
+    sold_number: Option<i64>,
+}
+
+/// Rows about to be deleted for good, which the meta table keeps count of
+/// so check still adds up.
+#[derive(Debug, Default)]
+pub struct Purge {
+    orders: i64,
+    evaluations: i64,
+    sold_number: i64,
+}
+
+impl Purge {
+    /// Counts the rows of `entity` matching `condition`.
+    pub async fn count<C, E>(db: &C, entity: E, condition: Condition) -> Result<Self, DbErr>
+    where
+        C: ConnectionTrait,
+        E: EntityTrait,
+        E::Model: Sync,
+    {
+        let rows = E::find().filter(condition.clone()).count(db).await? as i64;
+        if entity.table_name() != order::Entity.table_name() {
+            return Ok(Purge {
+                evaluations: rows,
+                ..Default::default()
+            });
+        }
+        if rows == 0 {
+            return Ok(Purge::default());
+        }
+        // only the orders placed since prepare took their units from the stock
+        let sold_number = match meta::find_version(db).await? {
+            Some(version) => order::Entity::find()
+                .select_only()
+                .column_as(
+                    Expr::cust(order::sold_number_sum(db.get_database_backend())),
+                    "sold_number",
+                )
+                .filter(condition)
+                .filter(order::Column::CreatedAt.gte(Timestamp::from_utc(version.prepared_at)))
+                .into_model::<SoldNumber>()
+                .one(db)
+                .await?
+                .and_then(|sold| sold.sold_number)
+                .unwrap_or(0),
+            None => 0,
+        };
+        Ok(Purge {
+            orders: rows,
+            evaluations: 0,
+            sold_number,
+        })
+    }
+
+    /// Adds the rows to the purged counts once they are deleted.
+    pub async fn record<C: ConnectionTrait>(self, db: &C) -> Result<(), DbErr> {
+        if self.orders == 0 && self.evaluations == 0 {
+            return Ok(());
+        }
+        meta::record_purged(db, self.orders, self.evaluations, self.sold_number).await
+    }
+}
+
+#[derive(FromQueryResult)]
+struct ArchiveKey {
+    id: Id,
+}
+
+/// Archives up to `batch_size` of the oldest rows of `entity` created before
+/// `cutoff`, returning how many rows were archived.
+pub async fn archive_batch<E, C>(
+    txn: &DatabaseTransaction,
+    entity: E,
+    created_at: C,
+    cutoff: Timestamp,
+    batch_size: u64,
+    mode: ArchiveMode,
+) -> Result<u32, DbErr>
+where
+    E: EntityTrait,
+    E::Model: Sync,
+    C: ColumnTrait,
+{
+    let key = E::PrimaryKey::iter()
+        .next()
+        .expect("entity without primary key")
+        .into_column();
+    let ids: Vec<Id> = E::find()
+        .select_only()
+        .column_as(key, "id")
+        .filter(created_at.lt(cutoff))
+        .order_by_asc(created_at)
+        .limit(batch_size)
+        .into_model::<ArchiveKey>()
+        .all(txn)
+        .await?
+        .into_iter()
+        .map(|row| row.id)
+        .collect();
+    if ids.is_empty() {
+        return Ok(0);
+    }
+    if mode == ArchiveMode::Move {
+        let mut insert_stmt = Query::insert();
+        insert_stmt
+            .into_table(Alias::new(&archive_table(entity.table_name())))
+            .columns(E::Column::iter())
+            .select_from(
+                Query::select()
+                    .columns(E::Column::iter())
+                    .from(entity)
+                    .and_where(key.is_in(ids.iter().copied()))
+                    .to_owned(),
+            )
+            .map_err(|err| DbErr::Custom(err.to_string()))?;
+        txn.execute(txn.get_database_backend().build(&insert_stmt))
+            .await?;
+    } else {
+        Purge::count(
+            txn,
+            entity,
+            Condition::all().add(key.is_in(ids.iter().copied())),
+        )
+        .await?
+        .record(txn)
+        .await?;
+    }
+    E::delete_many()
+        .filter(key.is_in(ids.iter().copied()))
+        .exec(txn)
+        .await?;
+    Ok(ids.len() as u32)
+}
@@ -1,29 +1,48 @@
-use chrono::Local;
+use super::key::{new_id, Id};
+use super::types::Timestamp;
 use fakeit::name;
-use sea_orm::{entity::prelude::*, ActiveValue::NotSet, Set};
+use sea_orm::{entity::prelude::*, Set};
 
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
-#[sea_orm(table_name = "tiny_orders_consumer")]
 pub struct Model {
-    #[sea_orm(primary_key, auto_increment = true)]
-    pub id: i64,
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub id: Id,
     pub name: String,
-    pub updated_at: DateTime,
-    pub created_at: DateTime,
+    pub updated_at: Timestamp,
+    pub created_at: Timestamp,
 }
 
+entity_name!("consumer");
+
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
-pub enum Relation {}
+pub enum Relation {
+    #[sea_orm(has_many = "super::order::Entity")]
+    Order,
+    #[sea_orm(has_many = "super::evaluation::Entity")]
+    Evaluation,
+}
+
+impl Related<super::order::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Order.def()
+    }
+}
+
+impl Related<super::evaluation::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Evaluation.def()
+    }
+}
 
 impl ActiveModelBehavior for ActiveModel {}
 
 impl ActiveModel {
     pub fn rand_fake_new() -> Self {
-        let create_at = Local::now().naive_local();
+        let create_at = Timestamp::now();
         Self {
-            id: NotSet,
+            id: new_id(),
             name: Set(name::full()),
-            updated_at: Set(create_at.clone()),
+            updated_at: Set(create_at),
             created_at: Set(create_at),
         }
     }
@@ -1,34 +1,61 @@
-use chrono::Local;
+use super::key::{new_id, Id};
+use super::types::Timestamp;
 use fakeit::hipster;
-use sea_orm::{entity::prelude::*, ActiveValue::NotSet, Set};
+use sea_orm::{entity::prelude::*, Set};
 
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
-#[sea_orm(table_name = "tiny_orders_evaluation")]
 pub struct Model {
-    #[sea_orm(primary_key, auto_increment = true)]
-    pub id: i64,
-    pub consumer_id: i64,
-    pub commodity_id: i64,
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub id: Id,
+    pub consumer_id: Id,
+    pub commodity_id: Id,
     pub evaluation: String,
-    pub updated_at: DateTime,
-    pub created_at: DateTime,
+    pub updated_at: Timestamp,
+    pub created_at: Timestamp,
 }
 
+entity_name!("evaluation");
+
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
-pub enum Relation {}
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::consumer::Entity",
+        from = "Column::ConsumerId",
+        to = "super::consumer::Column::Id"
+    )]
+    Consumer,
+    #[sea_orm(
+        belongs_to = "super::commodity::Entity",
+        from = "Column::CommodityId",
+        to = "super::commodity::Column::Id"
+    )]
+    Commodity,
+}
+
+impl Related<super::consumer::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Consumer.def()
+    }
+}
+
+impl Related<super::commodity::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Commodity.def()
+    }
+}
 
 impl ActiveModelBehavior for ActiveModel {}
 
 impl ActiveModel {
-    pub fn rand_fake_new(consumer_id: i64, commodity_id: i64) -> Self {
-        let create_at = Local::now().naive_local();
+    pub fn rand_fake_new(consumer_id: Id, commodity_id: Id) -> Self {
+        let create_at = Timestamp::now();
         Self {
             consumer_id: Set(consumer_id),
             commodity_id: Set(commodity_id),
             evaluation: Set(hipster::sentence(10)),
-            updated_at: Set(create_at.clone()),
+            updated_at: Set(create_at),
             created_at: Set(create_at),
-            id: NotSet,
+            id: new_id(),
         }
     }
 }
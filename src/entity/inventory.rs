@@ -1,17 +1,36 @@
+use super::key::Id;
+use super::types::Timestamp;
 use sea_orm::{entity::prelude::*, ActiveValue::NotSet, Set};
 
+/// Stock every commodity starts with.
+pub const INITIAL_STOCK: i64 = 100000;
+
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
-#[sea_orm(table_name = "tiny_orders_inventory")]
 pub struct Model {
-    #[sea_orm(primary_key)]
-    pub commodity_id: i64,
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub commodity_id: Id,
     pub inventory: i64,
-    pub updated_at: DateTime,
-    pub created_at: DateTime,
+    pub updated_at: Timestamp,
+    pub created_at: Timestamp,
 }
 
+entity_name!("inventory");
+
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
-pub enum Relation {}
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::commodity::Entity",
+        from = "Column::CommodityId",
+        to = "super::commodity::Column::Id"
+    )]
+    Commodity,
+}
+
+impl Related<super::commodity::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Commodity.def()
+    }
+}
 
 impl ActiveModelBehavior for ActiveModel {}
 
@@ -19,7 +38,7 @@ impl ActiveModel {
     pub fn rand_fake_new() -> Self {
         Self {
             commodity_id: NotSet,
-            inventory: Set(100000),
+            inventory: Set(INITIAL_STOCK),
             updated_at: NotSet,
             created_at: NotSet,
         }
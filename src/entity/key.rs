@@ -0,0 +1,431 @@
+use crate::dialect::Dialect;
+use sea_orm::prelude::Uuid;
+use sea_orm::{
+    sea_query::{Alias, ColumnDef, ColumnSpec, ColumnType, Nullable, ValueType, ValueTypeErr},
+    ActiveValue::{self, NotSet},
+    ColumnTrait, DatabaseConnection, DbErr, EntityTrait, FromQueryResult, Iterable,
+    PrimaryKeyToColumn, QueryFilter, QueryOrder, QueryResult, QuerySelect, Set, TryFromU64,
+    TryGetError, TryGetable, Value,
+};
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::OnceLock;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Column type the entities declare for keys, replaced by the type of the
+/// key strategy when the tables are created.
+pub const KEY_COLUMN_TYPE: &str = "tiny_orders_key";
+
+/// How the surrogate keys of commodity, consumer, order and evaluation are assigned.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyStrategy {
+    /// Sequential integers assigned by the database
+    AutoIncrement,
+    /// Integers scattered by TiDB's AUTO_RANDOM
+    AutoRandom,
+    /// Integers assigned by CockroachDB's unique_rowid()
+    UniqueRowid,
+    /// Random UUIDs generated by the client
+    UuidV4,
+    /// Time-ordered UUIDs generated by the client
+    UuidV7,
+    /// Snowflake-style integers generated by the client
+    Snowflake,
+}
+
+impl KeyStrategy {
+    pub fn name(&self) -> &'static str {
+        match self {
+            KeyStrategy::AutoIncrement => "auto-increment",
+            KeyStrategy::AutoRandom => "auto-random",
+            KeyStrategy::UniqueRowid => "unique-rowid",
+            KeyStrategy::UuidV4 => "uuid-v4",
+            KeyStrategy::UuidV7 => "uuid-v7",
+            KeyStrategy::Snowflake => "snowflake",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        <Self as clap::ValueEnum>::value_variants()
+            .iter()
+            .find(|strategy| strategy.name() == name)
+            .copied()
+    }
+
+    /// Fails for the strategies that only one database supports.
+    pub fn check(&self, dialect: Dialect) -> Result<(), DbErr> {
+        let supported = match self {
+            KeyStrategy::AutoRandom => dialect == Dialect::TiDb,
+            KeyStrategy::UniqueRowid => dialect == Dialect::CockroachDb,
+            _ => true,
+        };
+        if supported {
+            Ok(())
+        } else {
+            Err(DbErr::Custom(format!(
+                "Key strategy {} is not supported by {}",
+                self.name(),
+                dialect
+            )))
+        }
+    }
+
+    /// Gives the key columns of a create statement the type of this strategy.
+    /// A primary key that doesn't reference another table gets the clause
+    /// that generates it, and is left for a table level constraint if the
+    /// primary key is composite. The remaining columns are kept as is.
+    pub fn column_def(
+        &self,
+        column: &ColumnDef,
+        references: bool,
+        composite_primary_key: bool,
+    ) -> ColumnDef {
+        let is_key = matches!(
+            column.get_column_type(),
+            Some(ColumnType::Custom(name)) if name.to_string() == KEY_COLUMN_TYPE
+        );
+        if !is_key {
+            return column.clone();
+        }
+        let mut key_column = ColumnDef::new(Alias::new(&column.get_column_name()));
+        match self {
+            KeyStrategy::UuidV4 | KeyStrategy::UuidV7 => key_column.uuid(),
+            _ => key_column.big_integer(),
+        };
+        key_column.not_null();
+        let primary_key = column
+            .get_column_spec()
+            .iter()
+            .any(|spec| matches!(spec, ColumnSpec::PrimaryKey));
+        if !primary_key {
+            return key_column;
+        }
+        if !composite_primary_key {
+            key_column.primary_key();
+        }
+        if !references {
+            match self {
+                KeyStrategy::AutoIncrement => {
+                    key_column.auto_increment();
+                }
+                KeyStrategy::AutoRandom => {
+                    key_column.extra("AUTO_RANDOM".to_owned());
+                }
+                KeyStrategy::UniqueRowid => {
+                    key_column.extra("DEFAULT unique_rowid()".to_owned());
+                }
+                _ => {}
+            }
+        }
+        key_column
+    }
+}
+
+static STRATEGY: OnceLock<KeyStrategy> = OnceLock::new();
+
+/// Sets the key strategy new rows are inserted with, auto-increment if never set.
+pub fn set_strategy(strategy: KeyStrategy) {
+    STRATEGY.set(strategy).expect("key strategy already set");
+}
+
+pub fn strategy() -> KeyStrategy {
+    STRATEGY
+        .get()
+        .copied()
+        .unwrap_or(KeyStrategy::AutoIncrement)
+}
+
+/// Returns the key of a new row, left unset if the database assigns it.
+pub fn new_id() -> ActiveValue<Id> {
+    match strategy() {
+        KeyStrategy::AutoIncrement | KeyStrategy::AutoRandom | KeyStrategy::UniqueRowid => NotSet,
+        KeyStrategy::UuidV4 => Set(Id::Uuid(uuid_v4())),
+        KeyStrategy::UuidV7 => Set(Id::Uuid(uuid_v7())),
+        KeyStrategy::Snowflake => Set(Id::Int(snowflake())),
+    }
+}
+
+fn unix_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("system time before the unix epoch")
+        .as_millis() as u64
+}
+
+// Keys must not repeat across runs, so they don't draw from the seeded generators.
+fn uuid_v4() -> Uuid {
+    let random: u128 = ::rand::random();
+    Uuid::from_u128(random & !(0xf << 76) & !(0x3 << 62) | 0x4 << 76 | 0x2 << 62)
+}
+
+fn uuid_v7() -> Uuid {
+    let random: u128 = ::rand::random();
+    let millis = unix_millis() as u128 & 0xffff_ffff_ffff;
+    Uuid::from_u128(
+        millis << 80
+            | 0x7 << 76
+            | random & 0xfff << 64
+            | 0x2 << 62
+            | random & 0x3fff_ffff_ffff_ffff,
+    )
+}
+
+/// 2020-01-01 00:00:00 UTC, the epoch of the snowflake timestamps.
+const SNOWFLAKE_EPOCH: u64 = 1_577_836_800_000;
+
+/// The last timestamp and sequence handed out, as `millis << 12 | sequence`.
+static SNOWFLAKE_STATE: AtomicU64 = AtomicU64::new(0);
+static SNOWFLAKE_WORKER: OnceLock<u64> = OnceLock::new();
+
+/// 41 bits of milliseconds, 10 bits of worker and 12 bits of sequence. The
+/// worker is picked at random, so concurrent clients rarely share one.
+fn snowflake() -> i64 {
+    let worker = *SNOWFLAKE_WORKER.get_or_init(|| ::rand::random::<u64>() & 0x3ff);
+    let mut last = SNOWFLAKE_STATE.load(Ordering::Relaxed);
+    loop {
+        let millis = unix_millis() - SNOWFLAKE_EPOCH;
+        let next = if millis > last >> 12 {
+            millis << 12
+        } else {
+            // same millisecond, or the clock went back: continue the sequence,
+            // borrowing from the next millisecond once it is exhausted
+            last + 1
+        };
+        match SNOWFLAKE_STATE.compare_exchange_weak(
+            last,
+            next,
+            Ordering::Relaxed,
+            Ordering::Relaxed,
+        ) {
+            Ok(_) => return ((next >> 12) << 22 | worker << 12 | next & 0xfff) as i64,
+            Err(current) => last = current,
+        }
+    }
+}
+
+/// A key of any strategy, an integer or a UUID.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Id {
+    Int(i64),
+    Uuid(Uuid),
+}
+
+impl Id {
+    /// The key `part / parts` of the way from this one to `end`, both of the
+    /// same kind. UUIDs compare as their bytes, so they are spaced as such.
+    fn between(self, end: Id, part: u32, parts: u32) -> Id {
+        match (self, end) {
+            (Id::Int(start), Id::Int(end)) => Id::Int(
+                (start as i128 + (end as i128 - start as i128) * part as i128 / parts as i128)
+                    as i64,
+            ),
+            (Id::Uuid(start), Id::Uuid(end)) => {
+                let (start, end) = (start.as_u128(), end.as_u128());
+                Id::Uuid(Uuid::from_u128(
+                    start + (end.saturating_sub(start) / parts as u128) * part as u128,
+                ))
+            }
+            _ => self,
+        }
+    }
+}
+
+impl fmt::Display for Id {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Id::Int(id) => write!(f, "{}", id),
+            Id::Uuid(id) => write!(f, "{}", id),
+        }
+    }
+}
+
+impl From<i64> for Id {
+    fn from(id: i64) -> Self {
+        Id::Int(id)
+    }
+}
+
+impl From<Id> for Value {
+    fn from(id: Id) -> Self {
+        match id {
+            Id::Int(id) => id.into(),
+            Id::Uuid(id) => id.into(),
+        }
+    }
+}
+
+impl ValueType for Id {
+    fn try_from(v: Value) -> Result<Self, ValueTypeErr> {
+        match v {
+            Value::BigInt(Some(id)) => Ok(Id::Int(id)),
+            Value::Uuid(Some(id)) => Ok(Id::Uuid(*id)),
+            _ => Err(ValueTypeErr),
+        }
+    }
+
+    fn type_name() -> String {
+        "Id".to_owned()
+    }
+
+    fn column_type() -> ColumnType {
+        ColumnType::Custom(sea_orm::sea_query::SeaRc::new(Alias::new(KEY_COLUMN_TYPE)))
+    }
+}
+
+impl Nullable for Id {
+    fn null() -> Value {
+        Value::BigInt(None)
+    }
+}
+
+impl TryGetable for Id {
+    fn try_get(res: &QueryResult, pre: &str, col: &str) -> Result<Self, TryGetError> {
+        // the column type tells the strategy apart, try the integer first
+        match i64::try_get(res, pre, col) {
+            Ok(id) => Ok(Id::Int(id)),
+            Err(TryGetError::DbErr(_)) => Uuid::try_get(res, pre, col).map(Id::Uuid),
+            Err(err) => Err(err),
+        }
+    }
+}
+
+impl TryFromU64 for Id {
+    fn try_from_u64(n: u64) -> Result<Self, DbErr> {
+        Ok(Id::Int(n as i64))
+    }
+}
+
+#[derive(FromQueryResult)]
+struct KeyRow {
+    id: Id,
+}
+
+/// The most keys of a table the workload keeps in memory.
+const MAX_LOADED_KEYS: u32 = 100_000;
+/// Runs of keys a larger table is sampled in, evenly spaced over its key range.
+const SAMPLE_RUNS: u32 = 100;
+
+/// Loads the first `count` keys of `entity` in key order, or a sample of
+/// `MAX_LOADED_KEYS` of them if there are more. Keys are neither dense nor
+/// start at 1 for most strategies, so the workload picks from these instead
+/// of a range. The keys are leaked so the `Copy` callbacks of the workers can
+/// share them.
+pub async fn first_ids<E>(
+    db: &DatabaseConnection,
+    entity: E,
+    count: u32,
+) -> Result<&'static [Id], DbErr>
+where
+    E: EntityTrait,
+{
+    let ids = if count <= MAX_LOADED_KEYS {
+        load_ids::<E>(db, None, None, count).await?
+    } else {
+        sample_ids(db, entity, count).await?
+    };
+    Ok(Box::leak(ids.into_boxed_slice()))
+}
+
+/// Samples `MAX_LOADED_KEYS` of the first `count` keys in runs spread from
+/// the first key to the last one, bounded by the key range rather than the
+/// size of the table.
+async fn sample_ids<E>(db: &DatabaseConnection, entity: E, count: u32) -> Result<Vec<Id>, DbErr>
+where
+    E: EntityTrait,
+{
+    let first = match key_at::<E>(db, 0, false).await? {
+        Some(first) => first,
+        None => return Ok(Vec::new()),
+    };
+    // fewer rows than asked for end at the largest key
+    let last = match key_at::<E>(db, count as u64 - 1, false).await? {
+        Some(last) => last,
+        None => key_at::<E>(db, 0, true).await?.unwrap_or(first),
+    };
+    let mut ids = Vec::with_capacity(MAX_LOADED_KEYS as usize);
+    for run in 0..SAMPLE_RUNS {
+        let from = first.between(last, run, SAMPLE_RUNS);
+        ids.extend(load_ids::<E>(db, Some(from), Some(last), MAX_LOADED_KEYS / SAMPLE_RUNS).await?);
+    }
+    // runs over sparse keys overlap
+    ids.sort_unstable();
+    ids.dedup();
+    println!(
+        "Picking from {} keys sampled over the first {} of {}",
+        ids.len(),
+        count,
+        entity.table_name()
+    );
+    Ok(ids)
+}
+
+/// The key at `offset` in key order, from the end if `descending`.
+async fn key_at<E>(
+    db: &DatabaseConnection,
+    offset: u64,
+    descending: bool,
+) -> Result<Option<Id>, DbErr>
+where
+    E: EntityTrait,
+{
+    let column = key_column::<E>();
+    let query = E::find().select_only().column_as(column, "id");
+    let query = if descending {
+        query.order_by_desc(column)
+    } else {
+        query.order_by_asc(column)
+    };
+    let row = query
+        .offset(offset)
+        .limit(1)
+        .into_model::<KeyRow>()
+        .one(db)
+        .await?;
+    Ok(row.map(|row| row.id))
+}
+
+/// Loads up to `count` keys in key order, starting at `from` and ending at
+/// `to` if given, in pages.
+async fn load_ids<E>(
+    db: &DatabaseConnection,
+    from: Option<Id>,
+    to: Option<Id>,
+    count: u32,
+) -> Result<Vec<Id>, DbErr>
+where
+    E: EntityTrait,
+{
+    let column = key_column::<E>();
+    let mut ids: Vec<Id> = Vec::with_capacity(count as usize);
+    while ids.len() < count as usize {
+        let mut query = E::find()
+            .select_only()
+            .column_as(column, "id")
+            .order_by_asc(column)
+            .limit((count as u64 - ids.len() as u64).min(10000));
+        query = match (ids.last(), from) {
+            (Some(last), _) => query.filter(column.gt(*last)),
+            (None, Some(from)) => query.filter(column.gte(from)),
+            (None, None) => query,
+        };
+        if let Some(to) = to {
+            query = query.filter(column.lte(to));
+        }
+        let rows = query.into_model::<KeyRow>().all(db).await?;
+        if rows.is_empty() {
+            break;
+        }
+        ids.extend(rows.into_iter().map(|row| row.id));
+    }
+    Ok(ids)
+}
+
+fn key_column<E>() -> E::Column
+where
+    E: EntityTrait,
+{
+    E::PrimaryKey::iter()
+        .next()
+        .expect("entity without primary key")
+        .into_column()
+}
@@ -0,0 +1,158 @@
+use super::key::KeyStrategy;
+use super::types::ColumnTypes;
+use crate::migration::SCHEMA_VERSION;
+use chrono::Utc;
+use sea_orm::{
+    entity::prelude::*, sea_query::Expr, ConnectionTrait, FromQueryResult, QuerySelect, Set,
+};
+
+/// The metadata table holds a single row describing how the dataset was prepared.
+pub const META_ID: i32 = 1;
+
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub id: i32,
+    pub tool_version: String,
+    pub schema_version: i32,
+    /// 0 when the table sizes were given explicitly.
+    pub scale_factor: i64,
+    pub commodity_count: i64,
+    pub consumer_count: i64,
+    pub order_count: i64,
+    pub evaluation_count: i64,
+    pub initial_stock: i64,
+    /// The u64 seed, stored in a signed column with the same bits.
+    pub seed: i64,
+    /// In UTC, whatever the column types.
+    pub prepared_at: DateTime,
+    pub key_strategy: String,
+    pub column_types: String,
+    /// Orders deleted for good since prepare, by archiving or partition retention.
+    pub purged_order_count: i64,
+    pub purged_evaluation_count: i64,
+    /// Units sold by the purged orders placed since prepare.
+    pub purged_sold_number: i64,
+}
+
+entity_name!("meta");
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+impl ActiveModel {
+    #[allow(clippy::too_many_arguments)]
+    pub fn new(
+        scale_factor: Option<u32>,
+        commodity_count: u32,
+        consumer_count: u32,
+        order_count: u32,
+        evaluation_count: u32,
+        seed: u64,
+        key_strategy: KeyStrategy,
+        column_types: ColumnTypes,
+    ) -> Self {
+        Self {
+            id: Set(META_ID),
+            tool_version: Set(env!("CARGO_PKG_VERSION").to_owned()),
+            schema_version: Set(SCHEMA_VERSION),
+            scale_factor: Set(scale_factor.unwrap_or(0) as i64),
+            commodity_count: Set(commodity_count as i64),
+            consumer_count: Set(consumer_count as i64),
+            order_count: Set(order_count as i64),
+            evaluation_count: Set(evaluation_count as i64),
+            initial_stock: Set(super::inventory::INITIAL_STOCK),
+            seed: Set(seed as i64),
+            prepared_at: Set(Utc::now().naive_utc()),
+            key_strategy: Set(key_strategy.name().to_owned()),
+            column_types: Set(column_types.name().to_owned()),
+            purged_order_count: Set(0),
+            purged_evaluation_count: Set(0),
+            purged_sold_number: Set(0),
+        }
+    }
+
+    /// Replaces the stored metadata row with this one.
+    pub async fn save_meta<C: ConnectionTrait>(self, db: &C) -> Result<(), DbErr> {
+        Entity::delete_many().exec(db).await?;
+        self.insert(db).await?;
+        Ok(())
+    }
+}
+
+impl Model {
+    /// Whether the dataset was prepared with the schema this build expects.
+    pub fn is_compatible(&self) -> bool {
+        self.schema_version == SCHEMA_VERSION
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed as u64
+    }
+
+    pub fn key_strategy(&self) -> Option<KeyStrategy> {
+        KeyStrategy::from_name(&self.key_strategy)
+    }
+
+    pub fn column_types(&self) -> Option<ColumnTypes> {
+        ColumnTypes::from_name(&self.column_types)
+    }
+}
+
+pub async fn find<C: ConnectionTrait>(db: &C) -> Result<Option<Model>, DbErr> {
+    Entity::find_by_id(META_ID).one(db).await
+}
+
+/// Adds rows deleted for good to the purged counts.
+pub async fn record_purged<C: ConnectionTrait>(
+    db: &C,
+    orders: i64,
+    evaluations: i64,
+    sold_number: i64,
+) -> Result<(), DbErr> {
+    Entity::update_many()
+        .col_expr(
+            Column::PurgedOrderCount,
+            Expr::col(Column::PurgedOrderCount).add(orders),
+        )
+        .col_expr(
+            Column::PurgedEvaluationCount,
+            Expr::col(Column::PurgedEvaluationCount).add(evaluations),
+        )
+        .col_expr(
+            Column::PurgedSoldNumber,
+            Expr::col(Column::PurgedSoldNumber).add(sold_number),
+        )
+        .exec(db)
+        .await?;
+    Ok(())
+}
+
+/// The columns every schema version of the meta table has.
+#[derive(Debug, FromQueryResult)]
+pub struct Version {
+    pub tool_version: String,
+    pub schema_version: i32,
+    pub prepared_at: DateTime,
+}
+
+impl Version {
+    pub fn is_compatible(&self) -> bool {
+        self.schema_version == SCHEMA_VERSION
+    }
+}
+
+/// Reads only the version of the dataset, which works whatever schema
+/// version it was prepared with.
+pub async fn find_version<C: ConnectionTrait>(db: &C) -> Result<Option<Version>, DbErr> {
+    Entity::find_by_id(META_ID)
+        .select_only()
+        .column(Column::ToolVersion)
+        .column(Column::SchemaVersion)
+        .column(Column::PreparedAt)
+        .into_model::<Version>()
+        .one(db)
+        .await
+}
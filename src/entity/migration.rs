@@ -0,0 +1,18 @@
+use sea_orm::entity::prelude::*;
+
+/// Tracks which schema migrations have been applied to the database.
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub version: i32,
+    pub name: String,
+    /// In UTC, like `prepared_at` in the meta table.
+    pub applied_at: DateTime,
+}
+
+entity_name!("migration");
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
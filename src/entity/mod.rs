@@ -1,58 +1,409 @@
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
 use sea_orm::{
-    sea_query::{Table, TableCreateStatement},
-    ConnectionTrait, DatabaseBackend, DatabaseConnection, DbErr, EntityTrait, Schema, Statement,
-    TransactionTrait,
+    sea_query::{Alias, Expr, Index, IndexCreateStatement, Query, Table, TableCreateStatement},
+    ColumnTrait, ConnectionTrait, DatabaseBackend, DatabaseConnection, DbErr, EntityName,
+    EntityTrait, FromQueryResult, IdenStatic, Schema, Statement, TransactionTrait,
 };
+use std::sync::OnceLock;
 
+/// Declares the `Entity` of a table module. The table name is resolved on
+/// first use, so it carries the `--table-prefix` given on the command line.
+macro_rules! entity_name {
+    ($name:literal) => {
+        #[derive(Copy, Clone, Default, Debug, DeriveEntity)]
+        pub struct Entity;
+
+        impl EntityName for Entity {
+            fn table_name(&self) -> &str {
+                static TABLE_NAME: std::sync::OnceLock<String> = std::sync::OnceLock::new();
+                TABLE_NAME.get_or_init(|| super::prefixed_table_name($name))
+            }
+        }
+    };
+}
+
+pub mod archive;
 pub mod commodity;
 pub mod consumer;
 pub mod evaluation;
 pub mod inventory;
+pub mod key;
+pub mod meta;
+pub mod migration;
 pub mod order;
+pub mod partition;
+pub mod types;
+
+pub const DEFAULT_TABLE_PREFIX: &str = "tiny_orders_";
+
+static TABLE_PREFIX: OnceLock<String> = OnceLock::new();
+static SCHEMA: OnceLock<String> = OnceLock::new();
+
+/// Sets the table prefix and Postgres schema of the dataset. Must be called
+/// before any table name is used.
+pub fn set_naming(table_prefix: &str, schema: Option<&str>) {
+    TABLE_PREFIX
+        .set(table_prefix.to_owned())
+        .expect("table prefix already set");
+    if let Some(schema) = schema {
+        SCHEMA.set(schema.to_owned()).expect("schema already set");
+    }
+}
+
+fn prefixed_table_name(name: &str) -> String {
+    let prefix = TABLE_PREFIX
+        .get()
+        .map_or(DEFAULT_TABLE_PREFIX, String::as_str);
+    format!("{}{}", prefix, name)
+}
+
+/// Points the connections at `--schema` by setting their `search_path`, so
+/// tables, indexes and foreign keys all resolve in it.
+pub fn connect_url(db_url: &str) -> anyhow::Result<String> {
+    match SCHEMA.get() {
+        Some(schema) => {
+            if !db_url.starts_with("postgres") {
+                anyhow::bail!("--schema is only supported on Postgres");
+            }
+            // quoted like `create_schema` does, with the backslash escapes the
+            // server options need for spaces
+            let search_path = format!("\"{}\"", schema.replace('"', "\"\""))
+                .replace('\\', "\\\\")
+                .replace(' ', "\\ ");
+            let separator = if db_url.contains('?') { '&' } else { '?' };
+            Ok(format!(
+                "{}{}options[search_path]={}",
+                db_url,
+                separator,
+                utf8_percent_encode(&search_path, NON_ALPHANUMERIC)
+            ))
+        }
+        None => Ok(db_url.to_owned()),
+    }
+}
+
+/// Creates the `--schema` the dataset lives in if it doesn't exist yet.
+pub async fn create_schema(db: &DatabaseConnection) -> Result<(), DbErr> {
+    if let Some(schema) = SCHEMA.get() {
+        let create_schema = Statement::from_string(
+            DatabaseBackend::Postgres,
+            format!(
+                "CREATE SCHEMA IF NOT EXISTS \"{}\"",
+                schema.replace('"', "\"\"")
+            ),
+        );
+        db.execute(create_schema).await?;
+    }
+    Ok(())
+}
+
+/// How the relations between the entities are enforced by the database.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ForeignKeyMode {
+    /// Relations only exist in the entities, no constraint is created
+    Logical,
+    /// Relations are created as FOREIGN KEY constraints
+    Physical,
+}
 
-async fn _schema_setup<E>(db: &DatabaseConnection, entity: E) -> Result<(), DbErr>
+/// When the secondary indexes are created.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexMode {
+    /// Together with the tables, so the bulk load maintains them
+    BeforeLoad,
+    /// Once the bulk load has finished
+    AfterLoad,
+    /// Only keep the primary keys
+    None,
+}
+
+#[derive(clap::Args, Debug, Clone, Copy)]
+pub struct SchemaOptions {
+    /// Create the relations between tables as real foreign keys or leave them logical
+    #[clap(long, value_enum, default_value_t = ForeignKeyMode::Logical)]
+    pub foreign_keys: ForeignKeyMode,
+    /// Create the secondary indexes before or after the bulk load
+    #[clap(long, value_enum, default_value_t = IndexMode::AfterLoad)]
+    pub indexes: IndexMode,
+    /// How the primary keys are assigned
+    #[clap(long, value_enum, default_value_t = key::KeyStrategy::AutoIncrement)]
+    pub key_strategy: key::KeyStrategy,
+    /// Partition the order and evaluation tables
+    #[clap(long, value_enum, default_value_t = partition::PartitionMode::None)]
+    pub partition: partition::PartitionMode,
+    /// Number of hash partitions, or of daily range partitions created from today on
+    #[clap(long, default_value = "16")]
+    pub partitions: u32,
+    /// How prices and timestamps are stored
+    #[clap(long, value_enum, default_value_t = types::ColumnTypes::Typed)]
+    pub column_types: types::ColumnTypes,
+}
+
+impl SchemaOptions {
+    /// Fails for the options the database doesn't support.
+    pub fn check(&self, dialect: crate::dialect::Dialect) -> Result<(), DbErr> {
+        self.key_strategy.check(dialect)?;
+        partition::check(self, dialect)
+    }
+}
+
+/// Builds a named secondary index on `entity`, e.g. `idx-tiny_orders_order-consumer_id`
+/// with the default prefix.
+pub fn index<E, C>(entity: E, columns: &[C]) -> IndexCreateStatement
 where
     E: EntityTrait,
+    C: ColumnTrait,
 {
-    let schema = Schema::new(db.get_database_backend());
-    let create_stmt = schema.create_table_from_entity(entity);
-    let mut drop_stmt = Table::drop();
-    drop_stmt
-        .if_exists()
-        .table(create_stmt.get_table_name().expect("msg").clone());
+    let mut index = Index::create();
+    let mut name = format!("idx-{}", entity.table_name());
+    index.table(entity);
+    for column in columns {
+        index.col(*column);
+        name.push('-');
+        name.push_str(column.as_str());
+    }
+    index.name(&name).if_not_exists().take()
+}
 
-    let drop_stmt = db.get_database_backend().build(&drop_stmt);
-    let create_stmt = db.get_database_backend().build(&create_stmt);
+fn secondary_indexes() -> Vec<IndexCreateStatement> {
+    vec![
+        index(
+            order::Entity,
+            &[order::Column::ConsumerId, order::Column::CreatedAt],
+        ),
+        index(order::Entity, &[order::Column::CreatedAt]),
+        index(evaluation::Entity, &[evaluation::Column::CommodityId]),
+        index(evaluation::Entity, &[evaluation::Column::CreatedAt]),
+        index(commodity::Entity, &[commodity::Column::Price]),
+    ]
+}
+
+pub async fn create_indexes(db: &DatabaseConnection) -> Result<(), DbErr> {
+    // skip the indexes an earlier, interrupted prepare already created
+    for index in secondary_indexes() {
+        match db.execute(db.get_database_backend().build(&index)).await {
+            Err(err) if crate::dialect::dialect().is_duplicate_index(&err) => {}
+            result => {
+                result?;
+            }
+        }
+    }
+    println!("secondary indexes created");
+    Ok(())
+}
 
+fn create_table_stmt<E>(
+    backend: DatabaseBackend,
+    entity: E,
+    options: &SchemaOptions,
+    omitted: &[E::Column],
+) -> TableCreateStatement
+where
+    E: EntityTrait,
+{
+    let create_stmt = Schema::new(backend).create_table_from_entity(entity);
+    // rebuild the statement to give the keys, prices and timestamps their
+    // type and leave out the foreign keys unless they are physical
+    let mut stmt = Table::create();
+    stmt.table(create_stmt.get_table_name().expect("msg").clone());
+    let references: Vec<String> = create_stmt
+        .get_foreign_key_create_stmts()
+        .iter()
+        .flat_map(|foreign_key| foreign_key.get_foreign_key().get_columns())
+        .collect();
+    // a partitioned table needs the partition column in its primary key
+    let partition_column = if partition::is_partitioned(options, entity.table_name()) {
+        options.partition.column()
+    } else {
+        None
+    };
+    for column in create_stmt.get_columns() {
+        if omitted
+            .iter()
+            .any(|omitted| omitted.as_str() == column.get_column_name())
+        {
+            continue;
+        }
+        let references = references.contains(&column.get_column_name());
+        let column =
+            options
+                .key_strategy
+                .column_def(column, references, partition_column.is_some());
+        stmt.col(&mut options.column_types.column_def(&column, backend));
+    }
+    if let Some(partition_column) = partition_column {
+        stmt.primary_key(
+            Index::create()
+                .col(Alias::new("id"))
+                .col(Alias::new(partition_column)),
+        );
+    }
+    if options.foreign_keys == ForeignKeyMode::Physical {
+        for foreign_key in create_stmt.get_foreign_key_create_stmts() {
+            stmt.foreign_key(&mut foreign_key.clone());
+        }
+    }
+    stmt
+}
+
+pub async fn drop_table<E>(db: &DatabaseConnection, entity: E) -> Result<(), DbErr>
+where
+    E: EntityTrait,
+{
+    let mut drop_stmt = Table::drop();
+    drop_stmt.if_exists().table(entity);
+    db.execute(db.get_database_backend().build(&drop_stmt))
+        .await?;
+    Ok(())
+}
+
+/// Drops `column` of `entity`, written by hand for SQLite which sea-query
+/// has no statement for.
+pub async fn drop_column<E, C>(db: &DatabaseConnection, entity: E, column: C) -> Result<(), DbErr>
+where
+    E: EntityTrait,
+    C: ColumnTrait,
+{
+    let backend = db.get_database_backend();
+    let drop_stmt = match backend {
+        DatabaseBackend::Sqlite => Statement::from_string(
+            backend,
+            format!(
+                "ALTER TABLE \"{}\" DROP COLUMN \"{}\"",
+                entity.table_name(),
+                column.as_str()
+            ),
+        ),
+        _ => backend.build(Table::alter().table(entity).drop_column(column)),
+    };
     db.execute(drop_stmt).await?;
+    Ok(())
+}
 
-    if db.get_database_backend() == DatabaseBackend::Postgres {
-        // for crdb
+/// Creates the table of `entity` without the `omitted` columns, which later
+/// migrations add.
+pub async fn create_table<E>(
+    db: &DatabaseConnection,
+    entity: E,
+    options: &SchemaOptions,
+    omitted: &[E::Column],
+) -> Result<(), DbErr>
+where
+    E: EntityTrait,
+{
+    let dialect = crate::dialect::dialect();
+    let partitioned = partition::is_partitioned(options, entity.table_name());
+    let create_stmt = create_table_stmt(db.get_database_backend(), entity, options, omitted);
+    let mut create_stmt = db.get_database_backend().build(&create_stmt);
+    if partitioned {
+        create_stmt.sql.push(' ');
+        create_stmt
+            .sql
+            .push_str(&partition::partition_by(options, dialect));
+    }
+    let settings = dialect.create_table_settings();
+    if settings.is_empty() {
+        db.execute(create_stmt).await?;
+    } else {
         let txn = db.begin().await?;
-        let serial_normalization = Statement::from_string(
-            DatabaseBackend::Postgres,
-            "set local serial_normalization = sql_sequence;".to_owned(),
-        );
-        txn.execute(serial_normalization).await?;
+        for setting in settings {
+            txn.execute(Statement::from_string(
+                db.get_database_backend(),
+                setting.to_string(),
+            ))
+            .await?;
+        }
         txn.execute(create_stmt).await?;
         txn.commit().await?;
-    } else {
-        // for other db
-        db.execute(create_stmt).await?;
+    }
+    if partitioned {
+        partition::create_partitions(db, entity.table_name(), options).await?;
     }
     Ok(())
 }
 
-pub async fn schema_setup(db: &DatabaseConnection) -> Result<(), DbErr> {
-    _schema_setup(db, commodity::Entity).await?;
-    println!("commodity schema created");
-    _schema_setup(db, consumer::Entity).await?;
-    println!("consumer schema created");
-    _schema_setup(db, evaluation::Entity).await?;
-    println!("evaluation schema created");
-    _schema_setup(db, inventory::Entity).await?;
-    println!("inventory schema created");
-    _schema_setup(db, order::Entity).await?;
-    println!("order schema created");
+/// Drops every table of the benchmark, the referencing tables first so no
+/// foreign key blocks the drop.
+pub async fn drop_tables(db: &DatabaseConnection) -> Result<(), DbErr> {
+    drop_table(db, migration::Entity).await?;
+    drop_table(db, meta::Entity).await?;
+    drop_table(db, order::Entity).await?;
+    drop_table(db, evaluation::Entity).await?;
+    archive::drop_archive_table(db, order::Entity).await?;
+    archive::drop_archive_table(db, evaluation::Entity).await?;
+    drop_table(db, inventory::Entity).await?;
+    drop_table(db, consumer::Entity).await?;
+    drop_table(db, commodity::Entity).await?;
     Ok(())
 }
+
+/// A `COUNT(*)`, aliased `cnt` as `rows` is reserved on MySQL.
+#[derive(FromQueryResult)]
+struct Count {
+    cnt: i64,
+}
+
+/// Whether `table` exists where the dataset lives, the `--schema` on Postgres.
+pub async fn table_exists(db: &DatabaseConnection, table: &str) -> Result<bool, DbErr> {
+    let backend = db.get_database_backend();
+    let sql = match backend {
+        DatabaseBackend::Sqlite => {
+            "SELECT COUNT(*) AS cnt FROM sqlite_master WHERE type = 'table' AND name = ?"
+        }
+        DatabaseBackend::MySql => {
+            "SELECT COUNT(*) AS cnt FROM information_schema.tables \
+             WHERE table_schema = DATABASE() AND table_name = ?"
+        }
+        DatabaseBackend::Postgres => {
+            "SELECT COUNT(*) AS cnt FROM information_schema.tables \
+             WHERE table_schema = current_schema() AND table_name = $1"
+        }
+    };
+    let found =
+        Count::find_by_statement(Statement::from_sql_and_values(backend, sql, [table.into()]))
+            .one(db)
+            .await?;
+    Ok(found.is_some_and(|found| found.cnt > 0))
+}
+
+/// Counts the rows of `table`, `None` if it doesn't exist. Unlike counting
+/// through the entity this works whatever columns the table has.
+pub async fn count_rows(db: &DatabaseConnection, table: &str) -> Result<Option<u64>, DbErr> {
+    if !table_exists(db, table).await? {
+        return Ok(None);
+    }
+    let mut count_stmt = Query::select();
+    count_stmt
+        .expr_as(Expr::cust("COUNT(*)"), Alias::new("cnt"))
+        .from(Alias::new(table));
+    let count = Count::find_by_statement(db.get_database_backend().build(&count_stmt))
+        .one(db)
+        .await?;
+    Ok(Some(count.map_or(0, |count| count.cnt as u64)))
+}
+
+async fn table_rows(db: &DatabaseConnection, table: &str) -> Result<Option<(String, u64)>, DbErr> {
+    let rows = count_rows(db, table).await?;
+    Ok(rows.map(|rows| (table.to_owned(), rows)))
+}
+
+/// Lists the benchmark tables present in the database with their row
+/// counts, in the order `drop_tables` drops them.
+pub async fn existing_tables(db: &DatabaseConnection) -> Result<Vec<(String, u64)>, DbErr> {
+    let tables = [
+        migration::Entity.table_name().to_owned(),
+        meta::Entity.table_name().to_owned(),
+        order::Entity.table_name().to_owned(),
+        evaluation::Entity.table_name().to_owned(),
+        archive::archive_table(order::Entity.table_name()),
+        archive::archive_table(evaluation::Entity.table_name()),
+        inventory::Entity.table_name().to_owned(),
+        consumer::Entity.table_name().to_owned(),
+        commodity::Entity.table_name().to_owned(),
+    ];
+    let mut existing = Vec::new();
+    for table in &tables {
+        existing.extend(table_rows(db, table).await?);
+    }
+    Ok(existing)
+}
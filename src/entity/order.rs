@@ -1,32 +1,82 @@
-use chrono::Local;
-use sea_orm::{entity::prelude::*, ActiveValue::NotSet, Set};
+use super::key::{new_id, Id};
+use super::types::{Price, Timestamp, CURRENCY};
+use crate::rand::rand_i64;
+use sea_orm::{entity::prelude::*, ActiveValue::NotSet, DatabaseBackend, Set};
 
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
-#[sea_orm(table_name = "tiny_orders_order")]
 pub struct Model {
-    #[sea_orm(primary_key, auto_increment = true)]
-    pub id: i64,
-    pub consumer_id: i64,
-    pub commodity_id: i64,
-    pub sold_uint_price: i64,
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub id: Id,
+    pub consumer_id: Id,
+    pub commodity_id: Id,
+    pub sold_uint_price: Price,
+    pub currency: String,
     pub sold_number: i64,
-    pub created_at: DateTime,
+    pub created_at: Timestamp,
 }
 
+entity_name!("order");
+
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
-pub enum Relation {}
+pub enum Relation {
+    #[sea_orm(
+        belongs_to = "super::consumer::Entity",
+        from = "Column::ConsumerId",
+        to = "super::consumer::Column::Id"
+    )]
+    Consumer,
+    #[sea_orm(
+        belongs_to = "super::commodity::Entity",
+        from = "Column::CommodityId",
+        to = "super::commodity::Column::Id"
+    )]
+    Commodity,
+}
+
+impl Related<super::consumer::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Consumer.def()
+    }
+}
+
+impl Related<super::commodity::Entity> for Entity {
+    fn to() -> RelationDef {
+        Relation::Commodity.def()
+    }
+}
 
 impl ActiveModelBehavior for ActiveModel {}
 
 impl ActiveModel {
     pub fn new() -> Self {
         Self {
-            id: NotSet,
+            id: new_id(),
             consumer_id: NotSet,
             commodity_id: NotSet,
             sold_uint_price: NotSet,
+            currency: NotSet,
             sold_number: NotSet,
-            created_at: Set(Local::now().naive_local()),
+            created_at: Set(Timestamp::now()),
         }
     }
+
+    pub fn rand_fake_new(consumer_id: Id, commodity_id: Id) -> Self {
+        Self {
+            consumer_id: Set(consumer_id),
+            commodity_id: Set(commodity_id),
+            sold_uint_price: Set(Price::rand(1, 99)),
+            currency: Set(CURRENCY.to_owned()),
+            sold_number: Set(rand_i64(1, 5)),
+            ..Self::new()
+        }
+    }
+}
+
+/// `SUM(sold_number)`, cast back to a bigint from the decimal MySQL and
+/// Postgres sum into.
+pub fn sold_number_sum(backend: DatabaseBackend) -> &'static str {
+    match backend {
+        DatabaseBackend::MySql => "CAST(SUM(sold_number) AS SIGNED)",
+        _ => "CAST(SUM(sold_number) AS BIGINT)",
+    }
 }
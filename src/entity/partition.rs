@@ -0,0 +1,327 @@
+use super::types::{self, Timestamp};
+use super::{archive, evaluation, key::KeyStrategy, order, ForeignKeyMode, SchemaOptions};
+use crate::dialect::{self, Dialect};
+use chrono::{Duration, NaiveDate};
+use sea_orm::{
+    ColumnTrait, Condition, ConnectionTrait, DatabaseConnection, DbErr, EntityName, EntityTrait,
+    FromQueryResult, Statement,
+};
+
+/// How the append-heavy order and evaluation tables are partitioned.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PartitionMode {
+    /// Plain tables
+    None,
+    /// One partition per day of `created_at`
+    Range,
+    /// A fixed number of partitions by hash of `consumer_id`
+    Hash,
+}
+
+impl PartitionMode {
+    /// The column the tables are partitioned by, which has to be part of the primary key.
+    pub fn column(&self) -> Option<&'static str> {
+        match self {
+            PartitionMode::None => None,
+            PartitionMode::Range => Some("created_at"),
+            PartitionMode::Hash => Some("consumer_id"),
+        }
+    }
+}
+
+/// Fails for the partitioning the database can't create.
+pub fn check(options: &SchemaOptions, dialect: Dialect) -> Result<(), DbErr> {
+    if options.partition == PartitionMode::None {
+        return Ok(());
+    }
+    let mysql = match dialect {
+        Dialect::MySql | Dialect::TiDb | Dialect::MariaDb => true,
+        Dialect::Postgres | Dialect::YugabyteDb => false,
+        _ => {
+            return Err(DbErr::Custom(format!(
+                "Partitioning is not supported on {}",
+                dialect
+            )))
+        }
+    };
+    if mysql && options.foreign_keys == ForeignKeyMode::Physical {
+        return Err(DbErr::Custom(format!(
+            "{} doesn't support foreign keys on partitioned tables",
+            dialect
+        )));
+    }
+    Ok(())
+}
+
+/// Whether `table` is one of the tables `--partition` applies to.
+pub fn is_partitioned(options: &SchemaOptions, table: &str) -> bool {
+    options.partition != PartitionMode::None
+        && (table == order::Entity.table_name() || table == evaluation::Entity.table_name())
+}
+
+fn day_name(day: NaiveDate) -> String {
+    format!("p{}", day.format("%Y%m%d"))
+}
+
+fn parse_day_name(name: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(name.rsplit('_').next()?.strip_prefix('p')?, "%Y%m%d").ok()
+}
+
+/// The partitions of a new range partitioned table: everything before today
+/// goes to the partition named after yesterday, followed by one partition
+/// per day for `partitions` days.
+fn initial_days(partitions: u32) -> impl Iterator<Item = NaiveDate> {
+    let today = types::today();
+    (-1..partitions as i64).map(move |offset| today + Duration::days(offset))
+}
+
+/// The `PARTITION BY` clause appended to the create statement of `table`.
+/// MySQL defines the partitions along with it, Postgres creates them with
+/// `create_partitions` afterwards.
+pub fn partition_by(options: &SchemaOptions, dialect: Dialect) -> String {
+    let integer_keys = !matches!(
+        options.key_strategy,
+        KeyStrategy::UuidV4 | KeyStrategy::UuidV7
+    );
+    match (dialect, options.partition) {
+        (Dialect::Postgres | Dialect::YugabyteDb, PartitionMode::Range) => {
+            "PARTITION BY RANGE (\"created_at\")".to_owned()
+        }
+        (Dialect::Postgres | Dialect::YugabyteDb, _) => {
+            "PARTITION BY HASH (\"consumer_id\")".to_owned()
+        }
+        (_, PartitionMode::Range) => {
+            let partitions: Vec<String> = initial_days(options.partitions)
+                .map(|day| {
+                    format!(
+                        "PARTITION {} VALUES LESS THAN (TO_DAYS('{}'))",
+                        day_name(day),
+                        day.succ_opt().expect("date out of range")
+                    )
+                })
+                .collect();
+            format!(
+                "PARTITION BY RANGE (TO_DAYS(`created_at`)) ({})",
+                partitions.join(", ")
+            )
+        }
+        // HASH only takes integers, KEY hashes any type
+        (_, _) if integer_keys => format!(
+            "PARTITION BY HASH(`consumer_id`) PARTITIONS {}",
+            options.partitions
+        ),
+        (_, _) => format!(
+            "PARTITION BY KEY(`consumer_id`) PARTITIONS {}",
+            options.partitions
+        ),
+    }
+}
+
+/// Creates the partitions of a new Postgres partitioned table.
+pub async fn create_partitions(
+    db: &DatabaseConnection,
+    table: &str,
+    options: &SchemaOptions,
+) -> Result<(), DbErr> {
+    if !matches!(dialect::dialect(), Dialect::Postgres | Dialect::YugabyteDb) {
+        return Ok(());
+    }
+    match options.partition {
+        PartitionMode::None => {}
+        PartitionMode::Range => {
+            for (i, day) in initial_days(options.partitions).enumerate() {
+                let from = if i == 0 {
+                    "MINVALUE".to_owned()
+                } else {
+                    format!("'{}'", day)
+                };
+                add_postgres_partition(db, table, day, &from).await?;
+            }
+        }
+        PartitionMode::Hash => {
+            for remainder in 0..options.partitions {
+                execute(
+                    db,
+                    format!(
+                        "CREATE TABLE \"{}_p{}\" PARTITION OF \"{}\" FOR VALUES WITH (MODULUS {}, REMAINDER {})",
+                        table, remainder, table, options.partitions, remainder
+                    ),
+                )
+                .await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+async fn execute(db: &DatabaseConnection, sql: String) -> Result<(), DbErr> {
+    db.execute(Statement::from_string(db.get_database_backend(), sql))
+        .await?;
+    Ok(())
+}
+
+async fn add_postgres_partition(
+    db: &DatabaseConnection,
+    table: &str,
+    day: NaiveDate,
+    from: &str,
+) -> Result<(), DbErr> {
+    execute(
+        db,
+        format!(
+            "CREATE TABLE \"{}_{}\" PARTITION OF \"{}\" FOR VALUES FROM ({}) TO ('{}')",
+            table,
+            day_name(day),
+            table,
+            from,
+            day.succ_opt().expect("date out of range")
+        ),
+    )
+    .await
+}
+
+#[derive(FromQueryResult)]
+struct Partition {
+    name: String,
+}
+
+/// Lists the days of the daily partitions of `table`, empty if it isn't
+/// range partitioned.
+async fn partition_days(db: &DatabaseConnection, table: &str) -> Result<Vec<NaiveDate>, DbErr> {
+    let sql = match dialect::dialect() {
+        Dialect::Postgres | Dialect::YugabyteDb => format!(
+            "SELECT c.relname AS name FROM pg_inherits i JOIN pg_class c ON c.oid = i.inhrelid \
+             WHERE i.inhparent = '\"{}\"'::regclass",
+            table
+        ),
+        _ => format!(
+            "SELECT PARTITION_NAME AS name FROM information_schema.PARTITIONS \
+             WHERE TABLE_SCHEMA = DATABASE() AND TABLE_NAME = '{}' AND PARTITION_NAME IS NOT NULL",
+            table
+        ),
+    };
+    let partitions =
+        Partition::find_by_statement(Statement::from_string(db.get_database_backend(), sql))
+            .all(db)
+            .await?;
+    let mut days: Vec<NaiveDate> = partitions
+        .iter()
+        .filter_map(|partition| parse_day_name(&partition.name))
+        .collect();
+    days.sort();
+    Ok(days)
+}
+
+/// Creates the daily partitions of `entity` up to `ahead` days from today
+/// and drops the ones older than `retention` days.
+async fn maintain_table<E, C>(
+    db: &DatabaseConnection,
+    entity: E,
+    created_at: C,
+    ahead: u32,
+    retention: Option<u32>,
+) -> Result<(), DbErr>
+where
+    E: EntityTrait,
+    E::Model: Sync,
+    C: ColumnTrait,
+{
+    let table = entity.table_name();
+    let days = partition_days(db, table).await?;
+    let last = match days.last() {
+        Some(last) => *last,
+        None => return Ok(()),
+    };
+    let today = types::today();
+    let postgres = matches!(dialect::dialect(), Dialect::Postgres | Dialect::YugabyteDb);
+    let mut day = last;
+    while day < today + Duration::days(ahead as i64 - 1) {
+        day = day.succ_opt().expect("date out of range");
+        if postgres {
+            add_postgres_partition(db, table, day, &format!("'{}'", day)).await?;
+        } else {
+            execute(
+                db,
+                format!(
+                    "ALTER TABLE `{}` ADD PARTITION (PARTITION {} VALUES LESS THAN (TO_DAYS('{}')))",
+                    table,
+                    day_name(day),
+                    day.succ_opt().expect("date out of range")
+                ),
+            )
+            .await?;
+        }
+        println!("Created partition {} of {}", day_name(day), table);
+    }
+    let retention = match retention {
+        Some(retention) => retention,
+        None => return Ok(()),
+    };
+    for day in days
+        .into_iter()
+        .filter(|day| *day < today - Duration::days(retention as i64))
+    {
+        // the oldest partition also holds whatever came before its day
+        let next_day = Timestamp::start_of(day.succ_opt().expect("date out of range"));
+        let purge =
+            archive::Purge::count(db, entity, Condition::all().add(created_at.lt(next_day)))
+                .await?;
+        if postgres {
+            execute(db, format!("DROP TABLE \"{}_{}\"", table, day_name(day))).await?;
+        } else {
+            execute(
+                db,
+                format!("ALTER TABLE `{}` DROP PARTITION {}", table, day_name(day)),
+            )
+            .await?;
+        }
+        purge.record(db).await?;
+        println!("Dropped partition {} of {}", day_name(day), table);
+    }
+    Ok(())
+}
+
+/// Whether the dataset has daily partitions, which run out unless maintained.
+pub async fn is_range_partitioned(db: &DatabaseConnection) -> Result<bool, DbErr> {
+    if !has_range_partitions(dialect::dialect()) {
+        return Ok(false);
+    }
+    Ok(!partition_days(db, order::Entity.table_name())
+        .await?
+        .is_empty())
+}
+
+fn has_range_partitions(dialect: Dialect) -> bool {
+    matches!(
+        dialect,
+        Dialect::MySql | Dialect::TiDb | Dialect::MariaDb | Dialect::Postgres | Dialect::YugabyteDb
+    )
+}
+
+/// Keeps the daily partitions of the order and evaluation tables rolling.
+/// Tables that aren't range partitioned are left alone.
+pub async fn maintain(
+    db: &DatabaseConnection,
+    ahead: u32,
+    retention: Option<u32>,
+) -> Result<(), DbErr> {
+    if !has_range_partitions(dialect::dialect()) {
+        return Ok(());
+    }
+    maintain_table(
+        db,
+        order::Entity,
+        order::Column::CreatedAt,
+        ahead,
+        retention,
+    )
+    .await?;
+    maintain_table(
+        db,
+        evaluation::Entity,
+        evaluation::Column::CreatedAt,
+        ahead,
+        retention,
+    )
+    .await
+}
@@ -0,0 +1,252 @@
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use sea_orm::prelude::Decimal;
+use sea_orm::{
+    sea_query::{Alias, ColumnDef, ColumnType, Nullable, SeaRc, ValueType, ValueTypeErr},
+    DatabaseBackend, QueryResult, TryGetError, TryGetable, Value,
+};
+use std::fmt;
+use std::sync::OnceLock;
+
+/// Column types the entities declare for prices and timestamps, replaced by
+/// the types of the layout when the tables are created.
+pub const PRICE_COLUMN_TYPE: &str = "tiny_orders_price";
+pub const TIMESTAMP_COLUMN_TYPE: &str = "tiny_orders_timestamp";
+
+/// The currency of every price in the dataset.
+pub const CURRENCY: &str = "USD";
+
+/// How prices and timestamps are stored.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnTypes {
+    /// Decimal prices and UTC timestamps, TIMESTAMPTZ on Postgres
+    Typed,
+    /// Integer prices and local timestamps without time zone, as before schema version 3
+    Legacy,
+}
+
+impl ColumnTypes {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ColumnTypes::Typed => "typed",
+            ColumnTypes::Legacy => "legacy",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        <Self as clap::ValueEnum>::value_variants()
+            .iter()
+            .find(|types| types.name() == name)
+            .copied()
+    }
+
+    /// Gives the price and timestamp columns of a create statement the type
+    /// of this layout. The remaining columns are kept as is.
+    pub fn column_def(&self, column: &ColumnDef, backend: DatabaseBackend) -> ColumnDef {
+        let custom = match column.get_column_type() {
+            Some(ColumnType::Custom(name)) => name.to_string(),
+            _ => return column.clone(),
+        };
+        let mut typed_column = ColumnDef::new(Alias::new(&column.get_column_name()));
+        match (custom.as_str(), self) {
+            (PRICE_COLUMN_TYPE, ColumnTypes::Typed) => typed_column.decimal_len(12, 2),
+            (PRICE_COLUMN_TYPE, ColumnTypes::Legacy) => typed_column.big_integer(),
+            // MySQL only partitions TIMESTAMP columns by UNIX_TIMESTAMP(), keep
+            // DATETIME there and store UTC in it
+            (TIMESTAMP_COLUMN_TYPE, ColumnTypes::Typed) if backend != DatabaseBackend::MySql => {
+                typed_column.timestamp_with_time_zone()
+            }
+            (TIMESTAMP_COLUMN_TYPE, _) => typed_column.date_time(),
+            _ => return column.clone(),
+        };
+        typed_column.not_null();
+        typed_column
+    }
+}
+
+static COLUMN_TYPES: OnceLock<ColumnTypes> = OnceLock::new();
+
+/// Sets the layout prices and timestamps are read and written in, typed if never set.
+pub fn set_column_types(types: ColumnTypes) {
+    COLUMN_TYPES.set(types).expect("column types already set");
+}
+
+pub fn column_types() -> ColumnTypes {
+    COLUMN_TYPES.get().copied().unwrap_or(ColumnTypes::Typed)
+}
+
+/// The current day in the time zone of the timestamps.
+pub fn today() -> NaiveDate {
+    match column_types() {
+        ColumnTypes::Typed => Utc::now().date_naive(),
+        ColumnTypes::Legacy => Local::now().date_naive(),
+    }
+}
+
+/// A price in `CURRENCY`, whole units in the legacy layout.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Price(pub Decimal);
+
+impl Price {
+    /// A random price between `min` and `max` units, with cents unless legacy.
+    pub fn rand(min: i64, max: i64) -> Self {
+        match column_types() {
+            ColumnTypes::Typed => {
+                Price(Decimal::new(crate::rand::rand_i64(min * 100, max * 100), 2))
+            }
+            ColumnTypes::Legacy => Price(Decimal::from(crate::rand::rand_i64(min, max))),
+        }
+    }
+}
+
+impl fmt::Display for Price {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {}", self.0, CURRENCY)
+    }
+}
+
+impl From<Price> for Value {
+    fn from(price: Price) -> Self {
+        match column_types() {
+            ColumnTypes::Typed => price.0.into(),
+            ColumnTypes::Legacy => <i64 as TryFrom<Decimal>>::try_from(price.0.trunc())
+                .expect("price out of range")
+                .into(),
+        }
+    }
+}
+
+impl ValueType for Price {
+    fn try_from(v: Value) -> Result<Self, ValueTypeErr> {
+        match v {
+            Value::BigInt(Some(price)) => Ok(Price(Decimal::from(price))),
+            Value::Decimal(Some(price)) => Ok(Price(*price)),
+            _ => Err(ValueTypeErr),
+        }
+    }
+
+    fn type_name() -> String {
+        "Price".to_owned()
+    }
+
+    fn column_type() -> ColumnType {
+        ColumnType::Custom(SeaRc::new(Alias::new(PRICE_COLUMN_TYPE)))
+    }
+}
+
+impl Nullable for Price {
+    fn null() -> Value {
+        match column_types() {
+            ColumnTypes::Typed => Value::Decimal(None),
+            ColumnTypes::Legacy => Value::BigInt(None),
+        }
+    }
+}
+
+impl TryGetable for Price {
+    fn try_get(res: &QueryResult, pre: &str, col: &str) -> Result<Self, TryGetError> {
+        match column_types() {
+            // SQLite hands out decimals as floats
+            ColumnTypes::Typed => {
+                Decimal::try_get(res, pre, col).map(|price| Price(price.round_dp(2)))
+            }
+            ColumnTypes::Legacy => i64::try_get(res, pre, col).map(|price| Price(price.into())),
+        }
+    }
+}
+
+/// A point in time, stored as UTC or, in the legacy layout, as local time
+/// without time zone.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Timestamp(pub DateTime<Utc>);
+
+impl Timestamp {
+    pub fn now() -> Self {
+        Timestamp(Utc::now())
+    }
+
+    /// Reads a local time without time zone, taking the earlier of the two
+    /// readings when the clock went back.
+    pub fn from_local(local: NaiveDateTime) -> Self {
+        Timestamp(
+            Local
+                .from_local_datetime(&local)
+                .earliest()
+                .map(|local| local.with_timezone(&Utc))
+                // skipped by a DST jump, off by the jump at most
+                .unwrap_or_else(|| Utc.from_utc_datetime(&local)),
+        )
+    }
+
+    /// Reads a UTC time without time zone.
+    pub fn from_utc(utc: NaiveDateTime) -> Self {
+        Timestamp(Utc.from_utc_datetime(&utc))
+    }
+
+    /// The start of `day` in the time zone of the timestamps, see `today`.
+    pub fn start_of(day: NaiveDate) -> Self {
+        let midnight = day.and_hms_opt(0, 0, 0).expect("midnight exists");
+        match column_types() {
+            ColumnTypes::Typed => Timestamp(Utc.from_utc_datetime(&midnight)),
+            ColumnTypes::Legacy => Timestamp::from_local(midnight),
+        }
+    }
+}
+
+impl std::ops::Sub<chrono::Duration> for Timestamp {
+    type Output = Timestamp;
+
+    fn sub(self, duration: chrono::Duration) -> Timestamp {
+        Timestamp(self.0 - duration)
+    }
+}
+
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl From<Timestamp> for Value {
+    fn from(timestamp: Timestamp) -> Self {
+        match column_types() {
+            ColumnTypes::Typed => timestamp.0.into(),
+            ColumnTypes::Legacy => timestamp.0.with_timezone(&Local).naive_local().into(),
+        }
+    }
+}
+
+impl ValueType for Timestamp {
+    fn try_from(v: Value) -> Result<Self, ValueTypeErr> {
+        match v {
+            Value::ChronoDateTimeUtc(Some(timestamp)) => Ok(Timestamp(*timestamp)),
+            Value::ChronoDateTime(Some(timestamp)) => Ok(Timestamp::from_local(*timestamp)),
+            _ => Err(ValueTypeErr),
+        }
+    }
+
+    fn type_name() -> String {
+        "Timestamp".to_owned()
+    }
+
+    fn column_type() -> ColumnType {
+        ColumnType::Custom(SeaRc::new(Alias::new(TIMESTAMP_COLUMN_TYPE)))
+    }
+}
+
+impl Nullable for Timestamp {
+    fn null() -> Value {
+        match column_types() {
+            ColumnTypes::Typed => Value::ChronoDateTimeUtc(None),
+            ColumnTypes::Legacy => Value::ChronoDateTime(None),
+        }
+    }
+}
+
+impl TryGetable for Timestamp {
+    fn try_get(res: &QueryResult, pre: &str, col: &str) -> Result<Self, TryGetError> {
+        match column_types() {
+            ColumnTypes::Typed => DateTime::<Utc>::try_get(res, pre, col).map(Timestamp),
+            ColumnTypes::Legacy => NaiveDateTime::try_get(res, pre, col).map(Timestamp::from_local),
+        }
+    }
+}
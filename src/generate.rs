@@ -0,0 +1,576 @@
+use crate::entity::{
+    commodity, consumer, evaluation, inventory,
+    key::KeyStrategy,
+    meta, order,
+    types::{self, ColumnTypes},
+};
+use crate::prepare::{
+    scaled_count, COMMODITY_PER_SCALE, CONSUMER_PER_SCALE, EVALUATION_PER_SCALE, ORDER_PER_SCALE,
+};
+use crate::rand::{self, rand_history_datetime, rand_i64};
+use anyhow::{bail, Context, Result};
+use sea_orm::{ActiveModelTrait, EntityTrait, IdenStatic, Iterable, Set, Value};
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Csv,
+    #[cfg(feature = "parquet")]
+    Parquet,
+}
+
+impl Format {
+    fn extension(&self) -> &'static str {
+        match self {
+            Format::Csv => "csv",
+            #[cfg(feature = "parquet")]
+            Format::Parquet => "parquet",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Config {
+    scale_factor: Option<u32>,
+    commodity_count: u32,
+    consumer_count: u32,
+    order_count: u32,
+    evaluation_count: u32,
+    history_days: u32,
+    seed: u64,
+    concurrent: u32,
+    output_dir: PathBuf,
+    db_name: String,
+    file_size: u64,
+    format: Format,
+    column_types: ColumnTypes,
+}
+
+impl From<&super::Args> for Config {
+    fn from(args: &super::Args) -> Self {
+        match &args.command {
+            super::SubCommandArgs::Generate {
+                scale_factor,
+                commodity_count,
+                consumer_count,
+                order_count,
+                evaluation_count,
+                history_days,
+                seed,
+                output_dir,
+                db_name,
+                file_size_mb,
+                format,
+                column_types,
+            } => Self {
+                scale_factor: *scale_factor,
+                commodity_count: scaled_count(*commodity_count, *scale_factor, COMMODITY_PER_SCALE),
+                consumer_count: scaled_count(*consumer_count, *scale_factor, CONSUMER_PER_SCALE),
+                order_count: scaled_count(*order_count, *scale_factor, ORDER_PER_SCALE),
+                evaluation_count: scaled_count(
+                    *evaluation_count,
+                    *scale_factor,
+                    EVALUATION_PER_SCALE,
+                ),
+                history_days: *history_days,
+                seed: seed.unwrap_or_else(::rand::random),
+                concurrent: args.concurrent,
+                output_dir: output_dir.clone(),
+                db_name: db_name.clone(),
+                file_size: file_size_mb * 1024 * 1024,
+                format: *format,
+                column_types: *column_types,
+            },
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl Config {
+    fn history(&self) -> chrono::Duration {
+        chrono::Duration::days(self.history_days as i64)
+    }
+}
+
+/// Writes the dataset `prepare` would load as files named
+/// `{db}.{table}.{seq}.{ext}`, the layout TiDB Lightning picks up by default.
+/// The keys are always dense integers as with `--key-strategy auto-increment`.
+pub fn execute<T: Into<Config>>(config: T) -> Result<()> {
+    let config = config.into();
+    println!("Generating with seed {}", config.seed);
+    crate::rand::set_seed(config.seed);
+    types::set_column_types(config.column_types);
+    fs::create_dir_all(&config.output_dir).context("Failed to create output directory")?;
+    batch_write(
+        &config,
+        "commodity",
+        config.commodity_count,
+        |config, worker, ids| {
+            let mut commodity_writer = TableWriter::new(config, commodity::Entity, worker)?;
+            let mut inventory_writer = TableWriter::new(config, inventory::Entity, worker)?;
+            for id in ids {
+                let mut commodity_active = commodity::ActiveModel::rand_fake_new();
+                commodity_active.id = Set(id.into());
+                let mut inventory_active = inventory::ActiveModel::rand_fake_new();
+                inventory_active.commodity_id = Set(id.into());
+                inventory_active.updated_at = commodity_active.created_at.clone();
+                inventory_active.created_at = commodity_active.created_at.clone();
+                commodity_writer.write(&commodity_active)?;
+                inventory_writer.write(&inventory_active)?;
+            }
+            commodity_writer.finish()?;
+            inventory_writer.finish()
+        },
+    )
+    .context("Failed to generate commodity")?;
+    println!("Finished generate commodity.");
+    batch_write(
+        &config,
+        "consumer",
+        config.consumer_count,
+        |config, worker, ids| {
+            let mut writer = TableWriter::new(config, consumer::Entity, worker)?;
+            for id in ids {
+                let mut consumer_active = consumer::ActiveModel::rand_fake_new();
+                consumer_active.id = Set(id.into());
+                writer.write(&consumer_active)?;
+            }
+            writer.finish()
+        },
+    )
+    .context("Failed to generate consumer")?;
+    println!("Finished generate consumer.");
+    batch_write(
+        &config,
+        "order",
+        config.order_count,
+        |config, worker, ids| {
+            let mut writer = TableWriter::new(config, order::Entity, worker)?;
+            for id in ids {
+                let mut order_active = order::ActiveModel::rand_fake_new(
+                    rand_i64(1, config.consumer_count as i64).into(),
+                    rand_i64(1, config.commodity_count as i64).into(),
+                );
+                order_active.id = Set(id.into());
+                order_active.created_at = Set(rand_history_datetime(config.history()));
+                writer.write(&order_active)?;
+            }
+            writer.finish()
+        },
+    )
+    .context("Failed to generate order")?;
+    println!("Finished generate order.");
+    batch_write(
+        &config,
+        "evaluation",
+        config.evaluation_count,
+        |config, worker, ids| {
+            let mut writer = TableWriter::new(config, evaluation::Entity, worker)?;
+            for id in ids {
+                let mut evaluation_active = evaluation::ActiveModel::rand_fake_new(
+                    rand_i64(1, config.consumer_count as i64).into(),
+                    rand_i64(1, config.commodity_count as i64).into(),
+                );
+                let created_at = rand_history_datetime(config.history());
+                evaluation_active.id = Set(id.into());
+                evaluation_active.updated_at = Set(created_at);
+                evaluation_active.created_at = Set(created_at);
+                writer.write(&evaluation_active)?;
+            }
+            writer.finish()
+        },
+    )
+    .context("Failed to generate evaluation")?;
+    println!("Finished generate evaluation.");
+    let mut writer = TableWriter::new(&config, meta::Entity, 0)?;
+    writer.write(&meta::ActiveModel::new(
+        config.scale_factor,
+        config.commodity_count,
+        config.consumer_count,
+        config.order_count,
+        config.evaluation_count,
+        config.seed,
+        KeyStrategy::AutoIncrement,
+        config.column_types,
+    ))?;
+    writer.finish()?;
+    println!("Finished generate meta.");
+    Ok(())
+}
+
+/// Splits `1..=count` into one contiguous id range per worker thread, each
+/// drawing from its own random stream so the same seed reproduces the files.
+fn batch_write<F>(config: &Config, table: &'static str, count: u32, callback: F) -> Result<()>
+where
+    F: Fn(&Config, u32, std::ops::RangeInclusive<i64>) -> Result<()> + Send + Sync + Copy,
+{
+    let concurrent = config.concurrent.max(1);
+    thread::scope(|scope| {
+        let mut join_handle_vec = Vec::new();
+        let mut start = 1;
+        for i in 0..concurrent {
+            let mut unit_count = count / concurrent;
+            if i == concurrent - 1 {
+                unit_count += count - (unit_count * concurrent);
+            }
+            let ids = start..=(start + unit_count as i64 - 1);
+            start += unit_count as i64;
+            join_handle_vec.push(scope.spawn(move || {
+                rand::with_stream_sync(rand::stream(table, i, 0), || callback(config, i, ids))
+            }));
+        }
+        for handle in join_handle_vec {
+            handle.join().expect("generate worker panicked")?;
+        }
+        Ok(())
+    })
+}
+
+/// Writes the rows of one table produced by one worker, starting a new file
+/// whenever the current one grows past `Config::file_size`.
+struct TableWriter<'a, E: EntityTrait> {
+    config: &'a Config,
+    entity: E,
+    worker: u32,
+    seq: u32,
+    rows: u64,
+    sink: Option<Box<dyn Sink>>,
+}
+
+impl<'a, E: EntityTrait> TableWriter<'a, E> {
+    fn new(config: &'a Config, entity: E, worker: u32) -> Result<Self> {
+        let mut writer = Self {
+            config,
+            entity,
+            worker,
+            seq: 0,
+            rows: 0,
+            sink: None,
+        };
+        // Every table gets at least one (possibly empty) file.
+        writer.rotate()?;
+        Ok(writer)
+    }
+
+    fn write<A>(&mut self, active: &A) -> Result<()>
+    where
+        A: ActiveModelTrait<Entity = E>,
+    {
+        if self.rows > 0 && self.sink_mut().bytes_written() >= self.config.file_size {
+            self.rotate()?;
+        }
+        let row: Vec<Option<Value>> = E::Column::iter()
+            .map(|column| active.get(column).into_value())
+            .collect();
+        self.sink_mut().write_row(&row)?;
+        self.rows += 1;
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<()> {
+        if let Some(sink) = self.sink.take() {
+            sink.finish()?;
+        }
+        Ok(())
+    }
+
+    fn sink_mut(&mut self) -> &mut dyn Sink {
+        self.sink.as_deref_mut().expect("sink is always open")
+    }
+
+    fn rotate(&mut self) -> Result<()> {
+        if let Some(sink) = self.sink.take() {
+            sink.finish()?;
+        }
+        self.seq += 1;
+        self.rows = 0;
+        // the worker goes last at a fixed width, so a seq of any length stays unambiguous
+        let worker_width = (self.config.concurrent.max(1) - 1).to_string().len();
+        let path = self.config.output_dir.join(format!(
+            "{}.{}.{:06}{:0worker_width$}.{}",
+            self.config.db_name,
+            self.entity.table_name(),
+            self.seq,
+            self.worker,
+            self.config.format.extension(),
+            worker_width = worker_width
+        ));
+        self.sink = Some(match self.config.format {
+            Format::Csv => Box::new(CsvSink::new::<E>(&path)?),
+            #[cfg(feature = "parquet")]
+            Format::Parquet => Box::new(parquet_sink::ParquetSink::new::<E>(&path)?),
+        });
+        Ok(())
+    }
+}
+
+trait Sink {
+    fn write_row(&mut self, row: &[Option<Value>]) -> Result<()>;
+    fn bytes_written(&self) -> u64;
+    fn finish(self: Box<Self>) -> Result<()>;
+}
+
+struct CsvSink {
+    writer: BufWriter<File>,
+    bytes_written: u64,
+}
+
+impl CsvSink {
+    fn new<E: EntityTrait>(path: &Path) -> Result<Self> {
+        let file =
+            File::create(path).with_context(|| format!("Failed to create {}", path.display()))?;
+        let mut sink = Self {
+            writer: BufWriter::new(file),
+            bytes_written: 0,
+        };
+        let header: Vec<String> = E::Column::iter()
+            .map(|column| column.as_str().to_owned())
+            .collect();
+        sink.write_line(&header)?;
+        Ok(sink)
+    }
+
+    fn write_line(&mut self, fields: &[String]) -> Result<()> {
+        let mut line = fields
+            .iter()
+            .map(|field| csv_escape(field))
+            .collect::<Vec<_>>()
+            .join(",");
+        line.push('\n');
+        self.writer.write_all(line.as_bytes())?;
+        self.bytes_written += line.len() as u64;
+        Ok(())
+    }
+}
+
+impl Sink for CsvSink {
+    fn write_row(&mut self, row: &[Option<Value>]) -> Result<()> {
+        let fields = row
+            .iter()
+            .map(|value| match value {
+                Some(value) => csv_field(value),
+                None => Ok(r"\N".to_owned()),
+            })
+            .collect::<Result<Vec<_>>>()?;
+        self.write_line(&fields)
+    }
+
+    fn bytes_written(&self) -> u64 {
+        self.bytes_written
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+fn csv_field(value: &Value) -> Result<String> {
+    Ok(match value {
+        Value::Int(Some(v)) => v.to_string(),
+        Value::BigInt(Some(v)) => v.to_string(),
+        Value::String(Some(v)) => v.as_ref().clone(),
+        Value::Decimal(Some(v)) => v.to_string(),
+        Value::ChronoDateTime(Some(v)) => v.format("%Y-%m-%d %H:%M:%S%.6f").to_string(),
+        Value::ChronoDateTimeUtc(Some(v)) => v.format("%Y-%m-%d %H:%M:%S%.6f").to_string(),
+        Value::Int(None)
+        | Value::BigInt(None)
+        | Value::String(None)
+        | Value::Decimal(None)
+        | Value::ChronoDateTime(None)
+        | Value::ChronoDateTimeUtc(None) => r"\N".to_owned(),
+        _ => bail!("Unsupported value for csv: {:?}", value),
+    })
+}
+
+fn csv_escape(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_owned()
+    }
+}
+
+#[cfg(feature = "parquet")]
+mod parquet_sink {
+    use super::*;
+    use crate::entity::key::KEY_COLUMN_TYPE;
+    use crate::entity::types::{PRICE_COLUMN_TYPE, TIMESTAMP_COLUMN_TYPE};
+    use chrono::NaiveDate;
+    use parquet::data_type::{ByteArray, ByteArrayType, Int64Type};
+    use parquet::file::properties::WriterProperties;
+    use parquet::file::writer::SerializedFileWriter;
+    use parquet::schema::parser::parse_message_type;
+    use sea_orm::{ColumnTrait, ColumnType};
+    use std::sync::Arc;
+
+    const ROW_GROUP_SIZE: usize = 65536;
+
+    enum ColumnBuffer {
+        Int64(Vec<i64>),
+        ByteArray(Vec<ByteArray>),
+    }
+
+    pub struct ParquetSink {
+        writer: SerializedFileWriter<File>,
+        columns: Vec<ColumnBuffer>,
+        buffered_rows: usize,
+        buffered_bytes: u64,
+    }
+
+    impl ParquetSink {
+        pub fn new<E: EntityTrait>(path: &Path) -> Result<Self> {
+            let mut message_type = String::from("message schema {\n");
+            let mut columns = Vec::new();
+            for column in E::Column::iter() {
+                let name = column.as_str();
+                let (field, buffer) = match column.def().get_column_type() {
+                    ColumnType::BigInteger | ColumnType::Integer => (
+                        format!("REQUIRED INT64 {};", name),
+                        ColumnBuffer::Int64(Vec::new()),
+                    ),
+                    ColumnType::DateTime => (
+                        format!("REQUIRED INT64 {} (TIMESTAMP(MICROS,false));", name),
+                        ColumnBuffer::Int64(Vec::new()),
+                    ),
+                    // generated keys are always integers
+                    ColumnType::Custom(custom) if custom == KEY_COLUMN_TYPE => (
+                        format!("REQUIRED INT64 {};", name),
+                        ColumnBuffer::Int64(Vec::new()),
+                    ),
+                    ColumnType::Custom(custom) if custom == PRICE_COLUMN_TYPE => (
+                        match types::column_types() {
+                            ColumnTypes::Typed => {
+                                format!("REQUIRED INT64 {} (DECIMAL(12,2));", name)
+                            }
+                            ColumnTypes::Legacy => format!("REQUIRED INT64 {};", name),
+                        },
+                        ColumnBuffer::Int64(Vec::new()),
+                    ),
+                    ColumnType::Custom(custom) if custom == TIMESTAMP_COLUMN_TYPE => (
+                        format!(
+                            "REQUIRED INT64 {} (TIMESTAMP(MICROS,{}));",
+                            name,
+                            types::column_types() == ColumnTypes::Typed
+                        ),
+                        ColumnBuffer::Int64(Vec::new()),
+                    ),
+                    ColumnType::String(_) | ColumnType::Text => (
+                        format!("REQUIRED BYTE_ARRAY {} (UTF8);", name),
+                        ColumnBuffer::ByteArray(Vec::new()),
+                    ),
+                    column_type => bail!("Unsupported column type for parquet: {:?}", column_type),
+                };
+                message_type.push_str(&field);
+                message_type.push('\n');
+                columns.push(buffer);
+            }
+            message_type.push('}');
+            let schema = Arc::new(parse_message_type(&message_type)?);
+            let file = File::create(path)
+                .with_context(|| format!("Failed to create {}", path.display()))?;
+            let props = Arc::new(
+                WriterProperties::builder()
+                    .set_max_row_group_size(ROW_GROUP_SIZE)
+                    .build(),
+            );
+            Ok(Self {
+                writer: SerializedFileWriter::new(file, schema, props)?,
+                columns,
+                buffered_rows: 0,
+                buffered_bytes: 0,
+            })
+        }
+
+        fn flush_row_group(&mut self) -> Result<()> {
+            if self.buffered_rows == 0 {
+                return Ok(());
+            }
+            let mut row_group_writer = self.writer.next_row_group()?;
+            for buffer in self.columns.iter_mut() {
+                let mut column_writer = row_group_writer
+                    .next_column()?
+                    .expect("column count matches schema");
+                match buffer {
+                    ColumnBuffer::Int64(values) => {
+                        column_writer
+                            .typed::<Int64Type>()
+                            .write_batch(values, None, None)?;
+                        values.clear();
+                    }
+                    ColumnBuffer::ByteArray(values) => {
+                        column_writer
+                            .typed::<ByteArrayType>()
+                            .write_batch(values, None, None)?;
+                        values.clear();
+                    }
+                }
+                column_writer.close()?;
+            }
+            row_group_writer.close()?;
+            self.buffered_rows = 0;
+            self.buffered_bytes = 0;
+            Ok(())
+        }
+    }
+
+    impl Sink for ParquetSink {
+        fn write_row(&mut self, row: &[Option<Value>]) -> Result<()> {
+            for (buffer, value) in self.columns.iter_mut().zip(row) {
+                match (buffer, value) {
+                    (ColumnBuffer::Int64(values), Some(Value::Int(Some(v)))) => {
+                        values.push(*v as i64);
+                        self.buffered_bytes += 8;
+                    }
+                    (ColumnBuffer::Int64(values), Some(Value::BigInt(Some(v)))) => {
+                        values.push(*v);
+                        self.buffered_bytes += 8;
+                    }
+                    (ColumnBuffer::Int64(values), Some(Value::ChronoDateTime(Some(v)))) => {
+                        let epoch = NaiveDate::from_ymd_opt(1970, 1, 1)
+                            .and_then(|date| date.and_hms_opt(0, 0, 0))
+                            .expect("valid epoch");
+                        let micros = (**v - epoch)
+                            .num_microseconds()
+                            .context("Timestamp out of range")?;
+                        values.push(micros);
+                        self.buffered_bytes += 8;
+                    }
+                    (ColumnBuffer::Int64(values), Some(Value::Decimal(Some(v)))) => {
+                        let mut cents = **v;
+                        cents.rescale(2);
+                        values.push(cents.mantissa() as i64);
+                        self.buffered_bytes += 8;
+                    }
+                    (ColumnBuffer::Int64(values), Some(Value::ChronoDateTimeUtc(Some(v)))) => {
+                        values.push(v.timestamp_micros());
+                        self.buffered_bytes += 8;
+                    }
+                    (ColumnBuffer::ByteArray(values), Some(Value::String(Some(v)))) => {
+                        self.buffered_bytes += v.len() as u64;
+                        values.push(ByteArray::from(v.as_bytes().to_vec()));
+                    }
+                    (_, value) => bail!("Unsupported value for parquet: {:?}", value),
+                }
+            }
+            self.buffered_rows += 1;
+            if self.buffered_rows >= ROW_GROUP_SIZE {
+                self.flush_row_group()?;
+            }
+            Ok(())
+        }
+
+        fn bytes_written(&self) -> u64 {
+            self.writer.bytes_written() as u64 + self.buffered_bytes
+        }
+
+        fn finish(mut self: Box<Self>) -> Result<()> {
+            self.flush_row_group()?;
+            self.writer.close()?;
+            Ok(())
+        }
+    }
+}
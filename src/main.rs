@@ -1,11 +1,22 @@
+mod availability;
+mod check;
+mod cleanup;
+mod dialect;
 mod entity;
+mod generate;
+mod migration;
+mod pool;
 mod prepare;
+mod proxy;
 mod rand;
+mod rate_limit;
 mod run;
+mod schedule;
+mod session;
 mod statistics;
 
-use clap::{Parser, Subcommand};
-use sea_orm::Database;
+use clap::{CommandFactory, Parser, Subcommand};
+use std::path::PathBuf;
 
 /// Simple program to greet a person
 #[derive(Parser, Debug)]
@@ -13,48 +24,290 @@ use sea_orm::Database;
 struct Args {
     #[clap(subcommand)]
     command: SubCommandArgs,
-    #[clap(short = 'u', long)]
-    db_url: String,
+    /// Repeat or separate with commas to spread the workers of run over several
+    /// SQL nodes, the other subcommands use the first
+    #[clap(short = 'u', long, value_delimiter = ',', multiple_occurrences = true)]
+    db_url: Vec<String>,
     #[clap(short = 's', long, default_value = "1024")]
     txn_size: u32,
     #[clap(short = 'c', long, default_value = "4")]
     concurrent: u32,
+    /// Prefix of all table names, lets several datasets share one database
+    #[clap(long, default_value = entity::DEFAULT_TABLE_PREFIX)]
+    table_prefix: String,
+    /// Postgres schema holding the tables, created if missing
+    #[clap(long)]
+    schema: Option<String>,
+    #[clap(flatten)]
+    pool: pool::PoolOptions,
+    #[clap(flatten)]
+    proxy: proxy::ProxyOptions,
 }
 
 #[derive(Subcommand, Debug)]
 enum SubCommandArgs {
     Prepare {
+        /// Derive all table sizes from one number, explicit counts take precedence
+        #[clap(long, value_parser = clap::value_parser!(u32).range(..=prepare::MAX_SCALE_FACTOR as i64))]
+        scale_factor: Option<u32>,
+        #[clap(long, required_unless_present = "scale-factor")]
+        commodity_count: Option<u32>,
+        #[clap(long, required_unless_present = "scale-factor")]
+        consumer_count: Option<u32>,
+        /// Historical orders to backfill
         #[clap(long)]
-        commodity_count: u32,
+        order_count: Option<u32>,
+        /// Historical evaluations to backfill
         #[clap(long)]
-        consumer_count: u32,
+        evaluation_count: Option<u32>,
+        /// Spread the `created_at` of historical rows over this many past days
+        #[clap(long, default_value = "30")]
+        history_days: u32,
+        /// Seed for the random numbers, a random one is picked and recorded if omitted;
+        /// with the same --concurrent and --txn-size it draws the same prices, quantities
+        /// and referenced rows again, but not the fake texts, timestamps or generated keys
+        #[clap(long)]
+        seed: Option<u64>,
+        /// Continue an interrupted prepare: keep the schema and only insert missing rows,
+        /// with the seed, key strategy and column types it recorded
+        #[clap(long)]
+        resume: bool,
+        /// Drop the dataset's tables even if they already contain data
+        #[clap(long, conflicts_with = "resume")]
+        drop_existing: bool,
+        #[clap(flatten)]
+        schema: entity::SchemaOptions,
     },
+    // one way to pace the whole run, or rates of the services alone, the
+    // others then run unlimited
+    #[clap(group(clap::ArgGroup::new("load")))]
+    #[clap(group(clap::ArgGroup::new("pacing").required(true).multiple(true)))]
     Run {
+        /// Defaults to the count recorded by prepare
         #[clap(long)]
-        commodity_count: u32,
+        commodity_count: Option<u32>,
+        /// Defaults to the count recorded by prepare
         #[clap(long)]
-        consumer_count: u32,
+        consumer_count: Option<u32>,
         #[clap(long)]
         downgrade: bool,
+        /// Transactions or rows per second across all services, fractions allowed
+        #[clap(long, groups = &["load", "pacing"])]
+        rate_limit: Option<f64>,
+        /// Run as fast as the database allows, to find its peak throughput at --concurrent
+        #[clap(long, groups = &["load", "pacing"], conflicts_with_all = &["burst", "rate-unit"])]
+        unlimited: bool,
+        /// Ramp the rate linearly, as FROM:TO:SECONDS, then hold TO
+        #[clap(long, groups = &["load", "pacing"])]
+        ramp: Option<schedule::Ramp>,
+        /// Raise the rate every interval, as START:INCREMENT:SECONDS
+        #[clap(long, groups = &["load", "pacing"])]
+        step: Option<schedule::Step>,
+        /// Follow the rates of a file with one SECONDS,RATE pair per line
+        #[clap(long, groups = &["load", "pacing"])]
+        schedule_file: Option<PathBuf>,
+        /// Tokens the limiter lets through at once after being idle
+        #[clap(long, default_value = "1")]
+        burst: f64,
+        /// Whether --rate-limit counts transactions or changed rows
+        #[clap(long, value_enum, default_value_t = rate_limit::RateUnit::Txn)]
+        rate_unit: rate_limit::RateUnit,
+        /// Start transactions at the paced times even when the database falls
+        /// behind, and report the latency from those times as response time
+        #[clap(long, conflicts_with_all = &["unlimited", "burst"])]
+        open_loop: bool,
+        /// Workers of the orders service, --concurrent if omitted
+        #[clap(long)]
+        orders_concurrency: Option<u32>,
+        /// Workers of the evaluation service, --concurrent if omitted
+        #[clap(long)]
+        evaluation_concurrency: Option<u32>,
+        /// Workers of the change price service, --concurrent if omitted
+        #[clap(long)]
+        change_price_concurrency: Option<u32>,
+        /// Rate of the orders service alone, instead of a share of the shared limit
+        #[clap(long, group = "pacing")]
+        orders_rate: Option<f64>,
+        /// Rate of the evaluation service alone, instead of a share of the shared limit
+        #[clap(long, group = "pacing")]
+        evaluation_rate: Option<f64>,
+        /// Rate of the change price service alone, instead of a share of the shared limit
+        #[clap(long, group = "pacing")]
+        change_price_rate: Option<f64>,
+        /// Keep retrying transactions that lost their connection, as when a node fails
+        /// over, and report the outages and the availability of every endpoint
+        #[clap(long)]
+        resilient: bool,
+        /// Simulate this many shoppers instead of the services, each logged in as one
+        /// consumer, browsing commodities, maybe evaluating one and then ordering it
+        #[clap(
+            long,
+            group = "load",
+            conflicts_with_all = &[
+                "downgrade", "burst", "rate-unit", "open-loop",
+                "orders-concurrency", "evaluation-concurrency", "change-price-concurrency",
+                "orders-rate", "evaluation-rate", "change-price-rate",
+            ]
+        )]
+        sessions: Option<u32>,
+        /// Pause between the steps of a session: none, fixed:MS, uniform:MIN:MAX or
+        /// exp:MEAN [default: exp:1000]
+        #[clap(long)]
+        think_time: Option<session::ThinkTime>,
+        /// Commodities a session browses before ordering, as MIN:MAX [default: 1:5]
+        #[clap(long)]
+        browse_count: Option<session::BrowseCount>,
+        /// Chance that a session evaluates the commodity it orders [default: 0.3]
+        #[clap(long)]
+        evaluation_probability: Option<f64>,
+        /// Seconds between creating and dropping daily partitions of range partitioned tables
+        /// [default: 3600 if the tables are range partitioned]
+        #[clap(long)]
+        partition_maintenance_interval: Option<u64>,
+        /// Days of partitions to keep created ahead of today
+        #[clap(long, default_value = "7")]
+        partitions_ahead: u32,
+        /// Drop partitions older than this many days, deleting their orders and evaluations
+        #[clap(long)]
+        partition_retention_days: Option<u32>,
+        /// Archive orders and evaluations older than this many days while running
+        #[clap(long)]
+        archive_retention_days: Option<u32>,
+        /// What archiving does with the old rows
+        #[clap(long, value_enum, default_value_t = entity::archive::ArchiveMode::Delete)]
+        archive_mode: entity::archive::ArchiveMode,
+        /// Rows archived per transaction
+        #[clap(long, default_value = "1000")]
+        archive_batch_size: u64,
+        /// Archive transactions per second
+        #[clap(long, default_value = "1")]
+        archive_rate: u32,
+    },
+    /// Verify the prepared dataset against the recorded metadata
+    Check,
+    /// Drop all tables with the table prefix
+    Cleanup {
+        /// Only list the tables that would be dropped
+        #[clap(long)]
+        dry_run: bool,
+    },
+    /// Upgrade or downgrade the schema of an existing dataset
+    Migrate {
+        #[clap(subcommand)]
+        command: MigrateCommand,
+    },
+    /// Write the prepared dataset to files instead of a database
+    Generate {
+        #[clap(long, value_parser = clap::value_parser!(u32).range(..=prepare::MAX_SCALE_FACTOR as i64))]
+        scale_factor: Option<u32>,
+        #[clap(long, required_unless_present = "scale-factor")]
+        commodity_count: Option<u32>,
+        #[clap(long, required_unless_present = "scale-factor")]
+        consumer_count: Option<u32>,
         #[clap(long)]
-        rate_limit: u32,
+        order_count: Option<u32>,
+        #[clap(long)]
+        evaluation_count: Option<u32>,
+        #[clap(long, default_value = "30")]
+        history_days: u32,
+        #[clap(long)]
+        seed: Option<u64>,
+        #[clap(short = 'o', long, default_value = "tiny_orders_data")]
+        output_dir: PathBuf,
+        /// Database name used in the file names, e.g. `test.tiny_orders_order.0000010.csv`
+        #[clap(long, default_value = "test")]
+        db_name: String,
+        /// Start a new file once the current one exceeds this size
+        #[clap(long, default_value = "256")]
+        file_size_mb: u64,
+        #[clap(long, value_enum, default_value_t = generate::Format::Csv)]
+        format: generate::Format,
+        /// How prices and timestamps are written
+        #[clap(long, value_enum, default_value_t = entity::types::ColumnTypes::Typed)]
+        column_types: entity::types::ColumnTypes,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum MigrateCommand {
+    /// Apply the pending migrations
+    Up {
+        /// Number of migrations to apply, all if omitted
+        #[clap(short = 'n', long)]
+        num: Option<u32>,
+        #[clap(flatten)]
+        schema: entity::SchemaOptions,
     },
+    /// Revert the last applied migrations
+    Down {
+        /// Number of migrations to revert
+        #[clap(short = 'n', long, default_value = "1")]
+        num: u32,
+    },
+    /// Show which migrations are applied
+    Status,
 }
 
 #[tokio::main]
 async fn main() {
     let args = Args::parse();
-    let db = Database::connect(&args.db_url)
+    entity::set_naming(&args.table_prefix, args.schema.as_deref());
+    if let SubCommandArgs::Generate { .. } = args.command {
+        generate::execute(&args).expect("Failed to generate data");
+        return;
+    }
+    if args.db_url.is_empty() {
+        panic!("--db-url is required by this subcommand");
+    }
+    let mut db_urls = match args.command {
+        SubCommandArgs::Run { .. } => args.db_url.clone(),
+        _ => args.db_url[..1].to_vec(),
+    };
+    if args.proxy.proxy {
+        db_urls = proxy::start(&args.proxy, &db_urls)
+            .await
+            .expect("Failed to start the proxy");
+    }
+    let mut dbs = Vec::new();
+    for db_url in &db_urls {
+        let db_url = entity::connect_url(db_url).unwrap_or_else(|err| {
+            Args::command()
+                .error(clap::ErrorKind::ArgumentConflict, err)
+                .exit()
+        });
+        let db = args
+            .pool
+            .connect(db_url)
+            .await
+            .expect("Failed to connect to database");
+        dbs.push(db);
+    }
+    let db = &dbs[0];
+    dialect::detect(db)
         .await
-        .expect("Failed to connect to database");
+        .expect("Failed to detect the database");
     match args.command {
         SubCommandArgs::Prepare { .. } => {
-            prepare::execute(&db, &args)
+            prepare::execute(db, &args)
                 .await
                 .expect("Failed to prepare data");
         }
         SubCommandArgs::Run { .. } => {
-            run::execute(&db, &args).await.expect("Failed to run");
+            run::execute(&dbs, &args).await.expect("Failed to run");
+        }
+        SubCommandArgs::Migrate { .. } => {
+            migration::execute(db, &args)
+                .await
+                .expect("Failed to migrate");
+        }
+        SubCommandArgs::Cleanup { .. } => {
+            cleanup::execute(db, &args)
+                .await
+                .expect("Failed to cleanup");
+        }
+        SubCommandArgs::Check => {
+            check::execute(db).await.expect("Failed to check");
         }
+        SubCommandArgs::Generate { .. } => unreachable!(),
     }
 }
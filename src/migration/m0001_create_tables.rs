@@ -0,0 +1,121 @@
+use super::Migration;
+use crate::entity::{
+    commodity, consumer, create_indexes, create_table, drop_table, evaluation, inventory, meta,
+    order, IndexMode, SchemaOptions,
+};
+use futures::future::BoxFuture;
+use sea_orm::{
+    sea_query::{ColumnDef, Table},
+    ConnectionTrait, DatabaseConnection, DbErr,
+};
+
+pub const MIGRATION: Migration = Migration {
+    name: "create_tables",
+    up,
+    down,
+};
+
+fn up<'c>(
+    db: &'c DatabaseConnection,
+    options: &'c SchemaOptions,
+) -> BoxFuture<'c, Result<(), DbErr>> {
+    Box::pin(async move {
+        create_table(
+            db,
+            commodity::Entity,
+            options,
+            &[commodity::Column::Currency],
+        )
+        .await?;
+        println!("commodity schema created");
+        create_table(db, consumer::Entity, options, &[]).await?;
+        println!("consumer schema created");
+        create_table(db, inventory::Entity, options, &[]).await?;
+        println!("inventory schema created");
+        create_table(db, evaluation::Entity, options, &[]).await?;
+        println!("evaluation schema created");
+        create_table(db, order::Entity, options, &[order::Column::Currency]).await?;
+        println!("order schema created");
+        create_meta(db).await?;
+        println!("meta schema created");
+        if options.indexes == IndexMode::BeforeLoad {
+            create_indexes(db).await?;
+        }
+        Ok(())
+    })
+}
+
+/// Creates the meta table as of this version, the columns added since come
+/// with their own migrations.
+async fn create_meta(db: &DatabaseConnection) -> Result<(), DbErr> {
+    let create_stmt = Table::create()
+        .table(meta::Entity)
+        .col(
+            ColumnDef::new(meta::Column::Id)
+                .integer()
+                .not_null()
+                .primary_key(),
+        )
+        .col(
+            ColumnDef::new(meta::Column::ToolVersion)
+                .string()
+                .not_null(),
+        )
+        .col(
+            ColumnDef::new(meta::Column::SchemaVersion)
+                .integer()
+                .not_null(),
+        )
+        .col(
+            ColumnDef::new(meta::Column::ScaleFactor)
+                .big_integer()
+                .not_null(),
+        )
+        .col(
+            ColumnDef::new(meta::Column::CommodityCount)
+                .big_integer()
+                .not_null(),
+        )
+        .col(
+            ColumnDef::new(meta::Column::ConsumerCount)
+                .big_integer()
+                .not_null(),
+        )
+        .col(
+            ColumnDef::new(meta::Column::OrderCount)
+                .big_integer()
+                .not_null(),
+        )
+        .col(
+            ColumnDef::new(meta::Column::EvaluationCount)
+                .big_integer()
+                .not_null(),
+        )
+        .col(
+            ColumnDef::new(meta::Column::InitialStock)
+                .big_integer()
+                .not_null(),
+        )
+        .col(ColumnDef::new(meta::Column::Seed).big_integer().not_null())
+        .col(
+            ColumnDef::new(meta::Column::PreparedAt)
+                .date_time()
+                .not_null(),
+        )
+        .to_owned();
+    db.execute(db.get_database_backend().build(&create_stmt))
+        .await?;
+    Ok(())
+}
+
+fn down(db: &DatabaseConnection) -> BoxFuture<'_, Result<(), DbErr>> {
+    Box::pin(async move {
+        drop_table(db, meta::Entity).await?;
+        drop_table(db, order::Entity).await?;
+        drop_table(db, evaluation::Entity).await?;
+        drop_table(db, inventory::Entity).await?;
+        drop_table(db, consumer::Entity).await?;
+        drop_table(db, commodity::Entity).await?;
+        Ok(())
+    })
+}
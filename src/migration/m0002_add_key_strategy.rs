@@ -0,0 +1,39 @@
+use super::Migration;
+use crate::entity::{drop_column, key::KeyStrategy, meta, SchemaOptions};
+use futures::future::BoxFuture;
+use sea_orm::{
+    sea_query::{ColumnDef, Table},
+    ConnectionTrait, DatabaseConnection, DbErr,
+};
+
+pub const MIGRATION: Migration = Migration {
+    name: "add_key_strategy",
+    up,
+    down,
+};
+
+/// Records the key strategy in the meta table. Datasets from before used
+/// auto-increment keys.
+fn up<'c>(
+    db: &'c DatabaseConnection,
+    _options: &'c SchemaOptions,
+) -> BoxFuture<'c, Result<(), DbErr>> {
+    Box::pin(async move {
+        let alter_stmt = Table::alter()
+            .table(meta::Entity)
+            .add_column(
+                ColumnDef::new(meta::Column::KeyStrategy)
+                    .string()
+                    .not_null()
+                    .default(KeyStrategy::AutoIncrement.name()),
+            )
+            .to_owned();
+        db.execute(db.get_database_backend().build(&alter_stmt))
+            .await?;
+        Ok(())
+    })
+}
+
+fn down(db: &DatabaseConnection) -> BoxFuture<'_, Result<(), DbErr>> {
+    Box::pin(async move { drop_column(db, meta::Entity, meta::Column::KeyStrategy).await })
+}
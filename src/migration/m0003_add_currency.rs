@@ -0,0 +1,77 @@
+use super::Migration;
+use crate::entity::{
+    commodity, drop_column, meta, order,
+    types::{ColumnTypes, CURRENCY},
+    SchemaOptions,
+};
+use futures::future::BoxFuture;
+use sea_orm::{
+    sea_query::{ColumnDef, Table},
+    ConnectionTrait, DatabaseConnection, DbErr, EntityTrait,
+};
+
+pub const MIGRATION: Migration = Migration {
+    name: "add_currency",
+    up,
+    down,
+};
+
+/// Gives the prices a currency and records the column types in the meta
+/// table. Datasets from before have integer prices and local timestamps.
+fn up<'c>(
+    db: &'c DatabaseConnection,
+    _options: &'c SchemaOptions,
+) -> BoxFuture<'c, Result<(), DbErr>> {
+    Box::pin(async move {
+        add_currency(db, commodity::Entity).await?;
+        add_currency(db, order::Entity).await?;
+        let alter_stmt = Table::alter()
+            .table(meta::Entity)
+            .add_column(
+                ColumnDef::new(meta::Column::ColumnTypes)
+                    .string()
+                    .not_null()
+                    .default(ColumnTypes::Legacy.name()),
+            )
+            .to_owned();
+        db.execute(db.get_database_backend().build(&alter_stmt))
+            .await?;
+        Ok(())
+    })
+}
+
+async fn add_currency<E>(db: &DatabaseConnection, entity: E) -> Result<(), DbErr>
+where
+    E: EntityTrait,
+{
+    let alter_stmt = Table::alter()
+        .table(entity)
+        .add_column(
+            ColumnDef::new(commodity::Column::Currency)
+                .string()
+                .not_null()
+                .default(CURRENCY),
+        )
+        .to_owned();
+    db.execute(db.get_database_backend().build(&alter_stmt))
+        .await?;
+    Ok(())
+}
+
+/// Only datasets with the legacy column types can go back, the older
+/// builds can't read decimal prices.
+fn down(db: &DatabaseConnection) -> BoxFuture<'_, Result<(), DbErr>> {
+    Box::pin(async move {
+        if let Some(meta) = meta::find(db).await? {
+            if meta.column_types() != Some(ColumnTypes::Legacy) {
+                return Err(DbErr::Custom(format!(
+                    "The dataset has {} column types, only legacy ones can be migrated down",
+                    meta.column_types
+                )));
+            }
+        }
+        drop_column(db, meta::Entity, meta::Column::ColumnTypes).await?;
+        drop_column(db, order::Entity, order::Column::Currency).await?;
+        drop_column(db, commodity::Entity, commodity::Column::Currency).await
+    })
+}
@@ -0,0 +1,48 @@
+use super::Migration;
+use crate::entity::{drop_column, meta, SchemaOptions};
+use futures::future::BoxFuture;
+use sea_orm::{
+    sea_query::{ColumnDef, Table},
+    ConnectionTrait, DatabaseConnection, DbErr,
+};
+
+pub const MIGRATION: Migration = Migration {
+    name: "add_purged_counts",
+    up,
+    down,
+};
+
+const COLUMNS: [meta::Column; 3] = [
+    meta::Column::PurgedOrderCount,
+    meta::Column::PurgedEvaluationCount,
+    meta::Column::PurgedSoldNumber,
+];
+
+/// Records the orders and evaluations deleted for good in the meta table, so
+/// check can account for them. Datasets from before deleted none.
+fn up<'c>(
+    db: &'c DatabaseConnection,
+    _options: &'c SchemaOptions,
+) -> BoxFuture<'c, Result<(), DbErr>> {
+    Box::pin(async move {
+        // SQLite adds one column per statement
+        for column in COLUMNS {
+            let alter_stmt = Table::alter()
+                .table(meta::Entity)
+                .add_column(ColumnDef::new(column).big_integer().not_null().default(0))
+                .to_owned();
+            db.execute(db.get_database_backend().build(&alter_stmt))
+                .await?;
+        }
+        Ok(())
+    })
+}
+
+fn down(db: &DatabaseConnection) -> BoxFuture<'_, Result<(), DbErr>> {
+    Box::pin(async move {
+        for column in COLUMNS {
+            drop_column(db, meta::Entity, column).await?;
+        }
+        Ok(())
+    })
+}
@@ -0,0 +1,37 @@
+use super::Migration;
+use crate::entity::{evaluation, index, meta, IndexMode, SchemaOptions};
+use futures::future::BoxFuture;
+use sea_orm::{ConnectionTrait, DatabaseConnection, DbErr};
+
+pub const MIGRATION: Migration = Migration {
+    name: "index_evaluation_created_at",
+    up,
+    down,
+};
+
+/// Indexes the evaluations by `created_at`, which archiving picks the oldest
+/// by. A new dataset gets it with the other secondary indexes, only one
+/// already prepared, with a meta row, gets it here.
+fn up<'c>(
+    db: &'c DatabaseConnection,
+    options: &'c SchemaOptions,
+) -> BoxFuture<'c, Result<(), DbErr>> {
+    Box::pin(async move {
+        if options.indexes == IndexMode::None || meta::find_version(db).await?.is_none() {
+            return Ok(());
+        }
+        let index_stmt = index(evaluation::Entity, &[evaluation::Column::CreatedAt]);
+        match db
+            .execute(db.get_database_backend().build(&index_stmt))
+            .await
+        {
+            Err(err) if crate::dialect::dialect().is_duplicate_index(&err) => Ok(()),
+            result => result.map(|_| ()),
+        }
+    })
+}
+
+/// Keeps the index, the older builds don't mind it.
+fn down(_db: &DatabaseConnection) -> BoxFuture<'_, Result<(), DbErr>> {
+    Box::pin(async { Ok(()) })
+}
@@ -0,0 +1,44 @@
+use super::Migration;
+use crate::entity::{meta, types::Timestamp, SchemaOptions};
+use chrono::{DateTime, Local, NaiveDateTime};
+use futures::future::BoxFuture;
+use sea_orm::{sea_query::Expr, DatabaseConnection, DbErr, EntityTrait};
+
+pub const MIGRATION: Migration = Migration {
+    name: "prepared_at_in_utc",
+    up,
+    down,
+};
+
+/// Stores the time of prepare in UTC like the other timestamps, the datasets
+/// from before recorded it in local time.
+fn up<'c>(
+    db: &'c DatabaseConnection,
+    _options: &'c SchemaOptions,
+) -> BoxFuture<'c, Result<(), DbErr>> {
+    Box::pin(async move {
+        if let Some(version) = meta::find_version(db).await? {
+            let utc = Timestamp::from_local(version.prepared_at).0;
+            set_prepared_at(db, utc.naive_utc()).await?;
+        }
+        Ok(())
+    })
+}
+
+fn down(db: &DatabaseConnection) -> BoxFuture<'_, Result<(), DbErr>> {
+    Box::pin(async move {
+        if let Some(version) = meta::find_version(db).await? {
+            let local: DateTime<Local> = Timestamp::from_utc(version.prepared_at).0.into();
+            set_prepared_at(db, local.naive_local()).await?;
+        }
+        Ok(())
+    })
+}
+
+async fn set_prepared_at(db: &DatabaseConnection, prepared_at: NaiveDateTime) -> Result<(), DbErr> {
+    meta::Entity::update_many()
+        .col_expr(meta::Column::PreparedAt, Expr::value(prepared_at))
+        .exec(db)
+        .await?;
+    Ok(())
+}
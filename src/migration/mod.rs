@@ -0,0 +1,186 @@
+use crate::entity::{self, meta, migration, types::Timestamp, IndexMode, SchemaOptions};
+use anyhow::{Context, Result};
+use chrono::Utc;
+use futures::future::BoxFuture;
+use sea_orm::{
+    sea_query::Expr, ActiveModelTrait, ConnectionTrait, DatabaseConnection, DbErr, EntityTrait,
+    QueryOrder, Schema, Set,
+};
+
+mod m0001_create_tables;
+mod m0002_add_key_strategy;
+mod m0003_add_currency;
+mod m0004_add_purged_counts;
+mod m0005_index_evaluation_created_at;
+mod m0006_prepared_at_in_utc;
+
+/// A versioned schema change, applied by `up` and reverted by `down`.
+pub struct Migration {
+    pub name: &'static str,
+    pub up:
+        for<'c> fn(&'c DatabaseConnection, &'c SchemaOptions) -> BoxFuture<'c, Result<(), DbErr>>,
+    pub down: for<'c> fn(&'c DatabaseConnection) -> BoxFuture<'c, Result<(), DbErr>>,
+}
+
+/// All migrations in the order they are applied. The version of a migration
+/// is its position in this list, starting from 1.
+pub const MIGRATIONS: &[Migration] = &[
+    m0001_create_tables::MIGRATION,
+    m0002_add_key_strategy::MIGRATION,
+    m0003_add_currency::MIGRATION,
+    m0004_add_purged_counts::MIGRATION,
+    m0005_index_evaluation_created_at::MIGRATION,
+    m0006_prepared_at_in_utc::MIGRATION,
+];
+
+/// The schema version this build expects, i.e. all migrations applied.
+pub const SCHEMA_VERSION: i32 = MIGRATIONS.len() as i32;
+
+pub async fn execute(db: &DatabaseConnection, args: &super::Args) -> Result<()> {
+    match &args.command {
+        super::SubCommandArgs::Migrate { command } => match command {
+            super::MigrateCommand::Up { num, schema } => {
+                up(db, schema, *num).await?;
+                // no load follows, so the indexes left for after it are due now
+                if schema.indexes != IndexMode::None && applied_version(db).await? > 0 {
+                    entity::create_indexes(db)
+                        .await
+                        .context("Failed to create indexes")?;
+                }
+                Ok(())
+            }
+            super::MigrateCommand::Down { num } => down(db, *num).await,
+            super::MigrateCommand::Status => status(db).await,
+        },
+        _ => unreachable!(),
+    }
+}
+
+/// Drops all tables and applies every migration to start from an empty dataset.
+pub async fn fresh(db: &DatabaseConnection, options: &SchemaOptions) -> Result<()> {
+    options.check(crate::dialect::dialect())?;
+    entity::drop_tables(db)
+        .await
+        .context("Failed to drop tables")?;
+    println!("old schema dropped");
+    up(db, options, None).await
+}
+
+/// Applies the pending migrations, at most `num` of them if given.
+pub async fn up(db: &DatabaseConnection, options: &SchemaOptions, num: Option<u32>) -> Result<()> {
+    let mut version = applied_version(db).await?;
+    let pending = MIGRATIONS.iter().skip(version as usize);
+    for migration in pending.take(num.map_or(usize::MAX, |num| num as usize)) {
+        version += 1;
+        println!("Applying migration {:04}_{}", version, migration.name);
+        (migration.up)(db, options)
+            .await
+            .with_context(|| format!("Failed to apply migration {:04}", version))?;
+        migration::ActiveModel {
+            version: Set(version),
+            name: Set(migration.name.to_owned()),
+            applied_at: Set(Utc::now().naive_utc()),
+        }
+        .insert(db)
+        .await?;
+        sync_meta(db, version).await?;
+    }
+    println!("Schema is at version {}", version);
+    Ok(())
+}
+
+/// Reverts the last `num` applied migrations.
+pub async fn down(db: &DatabaseConnection, num: u32) -> Result<()> {
+    let mut version = applied_version(db).await?;
+    for _ in 0..num {
+        if version == 0 {
+            break;
+        }
+        let migration = &MIGRATIONS[version as usize - 1];
+        println!("Reverting migration {:04}_{}", version, migration.name);
+        (migration.down)(db)
+            .await
+            .with_context(|| format!("Failed to revert migration {:04}", version))?;
+        migration::Entity::delete_by_id(version).exec(db).await?;
+        version -= 1;
+        sync_meta(db, version).await?;
+    }
+    println!("Schema is at version {}", version);
+    Ok(())
+}
+
+pub async fn status(db: &DatabaseConnection) -> Result<()> {
+    let applied = applied(db).await?;
+    for (i, migration) in MIGRATIONS.iter().enumerate() {
+        match applied.get(i) {
+            Some(applied) => println!(
+                "{:04}_{} applied at {}",
+                i + 1,
+                migration.name,
+                applied.applied_at
+            ),
+            None => println!("{:04}_{} pending", i + 1, migration.name),
+        }
+    }
+    Ok(())
+}
+
+async fn applied_version(db: &DatabaseConnection) -> Result<i32> {
+    Ok(applied(db).await?.len() as i32)
+}
+
+/// Returns the applied migrations, creating the tracking table on first use.
+async fn applied(db: &DatabaseConnection) -> Result<Vec<migration::Model>> {
+    entity::create_schema(db)
+        .await
+        .context("Failed to create the schema")?;
+    let backend = db.get_database_backend();
+    let mut create_stmt = Schema::new(backend).create_table_from_entity(migration::Entity);
+    db.execute(backend.build(create_stmt.if_not_exists()))
+        .await
+        .context("Failed to create the migration table")?;
+    let applied = migration::Entity::find()
+        .order_by_asc(migration::Column::Version)
+        .all(db)
+        .await?;
+    if !applied.is_empty() {
+        return Ok(applied);
+    }
+    // Datasets prepared before the migrations existed only record their
+    // version in the meta table, take it as the baseline.
+    let meta = match meta::find_version(db).await {
+        Ok(Some(meta)) => meta,
+        _ => return Ok(applied),
+    };
+    println!("Found dataset at schema version {}", meta.schema_version);
+    let mut baseline = Vec::new();
+    for (i, migration) in MIGRATIONS.iter().enumerate() {
+        let version = i as i32 + 1;
+        if version > meta.schema_version {
+            break;
+        }
+        let applied = migration::ActiveModel {
+            version: Set(version),
+            name: Set(migration.name.to_owned()),
+            // recorded in local time by the builds from before the migrations
+            applied_at: Set(Timestamp::from_local(meta.prepared_at).0.naive_utc()),
+        }
+        .insert(db)
+        .await?;
+        baseline.push(applied);
+    }
+    Ok(baseline)
+}
+
+/// Keeps the schema version recorded in the meta table in step with the migrations.
+async fn sync_meta(db: &DatabaseConnection, version: i32) -> Result<()> {
+    if version == 0 {
+        // the meta table is gone with the first migration
+        return Ok(());
+    }
+    meta::Entity::update_many()
+        .col_expr(meta::Column::SchemaVersion, Expr::value(version))
+        .exec(db)
+        .await?;
+    Ok(())
+}
@@ -0,0 +1,122 @@
+use anyhow::{Context, Result};
+use chrono::Local;
+use sea_orm::{ConnectOptions, Database, DatabaseBackend, DatabaseConnection};
+use std::time::Duration;
+
+/// Sizing and timeouts of the connection pool.
+#[derive(clap::Args, Debug, Clone, Copy)]
+pub struct PoolOptions {
+    /// Connections the pool opens at most [default: 10, 1 on SQLite]
+    #[clap(long)]
+    pub max_connections: Option<u32>,
+    /// Connections the pool keeps open while idle
+    #[clap(long)]
+    pub min_connections: Option<u32>,
+    /// Seconds to wait for the first connection to the database
+    #[clap(long)]
+    pub connect_timeout: Option<u64>,
+    /// Seconds to wait for a pooled connection, opening one included [default: 30]
+    #[clap(long)]
+    pub acquire_timeout: Option<u64>,
+    /// Seconds before a connection idle in the pool is closed
+    #[clap(long)]
+    pub idle_timeout: Option<u64>,
+    /// Print every statement with its execution time
+    #[clap(long)]
+    pub log_statements: bool,
+    /// How run spreads its workers over several database URLs
+    #[clap(long, value_enum, default_value_t = Balance::RoundRobin)]
+    pub balance: Balance,
+}
+
+/// How the workers of `run` are spread over the database URLs.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Balance {
+    /// Hand the URLs out in turn
+    RoundRobin,
+    /// Pick a random URL for every worker
+    Random,
+}
+
+impl Balance {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Balance::RoundRobin => "round-robin",
+            Balance::Random => "random",
+        }
+    }
+}
+
+/// Where `url` points to, without the scheme, the credentials and the options.
+pub fn endpoint_name(url: &str) -> &str {
+    let rest = url.split_once("://").map_or(url, |(_, rest)| rest);
+    let rest = rest.split('?').next().unwrap_or(rest);
+    let authority = &rest[..rest.find('/').unwrap_or(rest.len())];
+    match authority.rfind('@') {
+        Some(at) => &rest[at + 1..],
+        None => rest,
+    }
+}
+
+impl PoolOptions {
+    /// The most connections the pool to `backend` opens.
+    pub fn max_connections(&self, backend: DatabaseBackend) -> u32 {
+        match (self.max_connections, backend) {
+            (Some(max_connections), _) => max_connections,
+            // sea-orm keeps SQLite to a single connection unless told otherwise
+            (None, DatabaseBackend::Sqlite) => 1,
+            (None, _) => 10,
+        }
+    }
+
+    pub async fn connect(&self, url: String) -> Result<DatabaseConnection> {
+        let mut options = ConnectOptions::new(url);
+        if let Some(max_connections) = self.max_connections {
+            options.max_connections(max_connections);
+        }
+        if let Some(min_connections) = self.min_connections {
+            options.min_connections(min_connections);
+        }
+        // what sea-orm calls the connect timeout is the acquire timeout of sqlx
+        if let Some(acquire_timeout) = self.acquire_timeout {
+            options.connect_timeout(Duration::from_secs(acquire_timeout));
+        }
+        if let Some(idle_timeout) = self.idle_timeout {
+            options.idle_timeout(Duration::from_secs(idle_timeout));
+        }
+        options.sqlx_logging(self.log_statements);
+        if self.log_statements {
+            // already set by the pool of an earlier URL
+            let _ = log::set_logger(&StatementLogger);
+            log::set_max_level(log::LevelFilter::Info);
+        }
+        // no driver has a connect timeout of its own
+        let connect = Database::connect(options);
+        let db = match self.connect_timeout {
+            Some(connect_timeout) => {
+                tokio::time::timeout(Duration::from_secs(connect_timeout), connect)
+                    .await
+                    .context("Timed out connecting to the database")??
+            }
+            None => connect.await?,
+        };
+        Ok(db)
+    }
+}
+
+/// Prints the statements sqlx logs, leaving the records of other crates out.
+struct StatementLogger;
+
+impl log::Log for StatementLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.target().starts_with("sqlx")
+    }
+
+    fn log(&self, record: &log::Record) {
+        if self.enabled(record.metadata()) {
+            println!("{} {}", Local::now(), record.args());
+        }
+    }
+
+    fn flush(&self) {}
+}
@@ -1,42 +1,115 @@
-use crate::entity::{commodity, consumer, inventory, schema_setup};
-use anyhow::{Context, Result};
+use crate::entity::{
+    self, commodity, consumer, create_indexes, evaluation, inventory,
+    key::{self, Id},
+    meta, order, types, IndexMode, SchemaOptions,
+};
+use crate::migration;
+use crate::rand::{self, rand_history_datetime, rand_pick};
+use anyhow::{bail, Context, Result};
 use futures::future::join_all;
-use sea_orm::ActiveModelTrait;
+use sea_orm::{ActiveModelTrait, EntityName, EntityTrait, PaginatorTrait};
 use sea_orm::{DatabaseConnection, DatabaseTransaction, DbErr, Set, TransactionTrait};
 use std::time::{Duration, Instant};
 use std::{future::Future, pin::Pin};
 
+/// Rows per unit of `--scale-factor`.
+pub const COMMODITY_PER_SCALE: u32 = 10_000;
+pub const CONSUMER_PER_SCALE: u32 = 100_000;
+pub const ORDER_PER_SCALE: u32 = 300_000;
+pub const EVALUATION_PER_SCALE: u32 = 100_000;
+/// The largest scale factor whose table sizes still fit the `u32` counts.
+pub const MAX_SCALE_FACTOR: u32 = u32::MAX / ORDER_PER_SCALE;
+
+/// Picks the explicit count if given, otherwise derives it from the scale factor.
+pub fn scaled_count(count: Option<u32>, scale_factor: Option<u32>, per_scale: u32) -> u32 {
+    count
+        .or_else(|| {
+            scale_factor.map(|scale_factor| {
+                scale_factor
+                    .checked_mul(per_scale)
+                    .expect("--scale-factor is capped at MAX_SCALE_FACTOR")
+            })
+        })
+        .unwrap_or(0)
+}
+
 pub struct Config {
+    scale_factor: Option<u32>,
     commodity_count: u32,
     consumer_count: u32,
+    order_count: u32,
+    evaluation_count: u32,
+    history_days: u32,
+    seed: u64,
     txn_size: u32,
     concurrent: u32,
+    resume: bool,
+    drop_existing: bool,
+    schema: SchemaOptions,
 }
 
 impl From<&super::Args> for Config {
     fn from(args: &super::Args) -> Self {
-        Self {
-            commodity_count: match args.command {
-                super::SubCommandArgs::Prepare {
-                    commodity_count, ..
-                } => commodity_count,
-                super::SubCommandArgs::Run { .. } => unreachable!(),
+        match args.command {
+            super::SubCommandArgs::Prepare {
+                scale_factor,
+                commodity_count,
+                consumer_count,
+                order_count,
+                evaluation_count,
+                history_days,
+                seed,
+                resume,
+                drop_existing,
+                schema,
+            } => Self {
+                scale_factor,
+                commodity_count: scaled_count(commodity_count, scale_factor, COMMODITY_PER_SCALE),
+                consumer_count: scaled_count(consumer_count, scale_factor, CONSUMER_PER_SCALE),
+                order_count: scaled_count(order_count, scale_factor, ORDER_PER_SCALE),
+                evaluation_count: scaled_count(
+                    evaluation_count,
+                    scale_factor,
+                    EVALUATION_PER_SCALE,
+                ),
+                history_days,
+                seed: seed.unwrap_or_else(::rand::random),
+                txn_size: args.txn_size,
+                concurrent: args.concurrent,
+                resume,
+                drop_existing,
+                schema,
             },
-            consumer_count: match args.command {
-                super::SubCommandArgs::Prepare { consumer_count, .. } => consumer_count,
-                super::SubCommandArgs::Run { .. } => unreachable!(),
-            },
-            txn_size: args.txn_size,
-            concurrent: args.concurrent,
+            _ => unreachable!(),
         }
     }
 }
 
 pub async fn execute<T: Into<Config>>(db: &DatabaseConnection, config: T) -> Result<()> {
-    let config = config.into();
-    // create table
-    schema_setup(db).await.context("Failed to setup schema")?;
-    println!("Finished setup schema.");
+    let mut config = config.into();
+    if config.resume {
+        load_recorded_settings(db, &mut config).await?;
+    }
+    println!("Preparing with seed {}", config.seed);
+    crate::rand::set_seed(config.seed);
+    key::set_strategy(config.schema.key_strategy);
+    types::set_column_types(config.schema.column_types);
+    if config.resume {
+        println!("Resuming prepare, skip setup schema.");
+    } else {
+        refuse_to_overwrite(db, &config).await?;
+        // create table
+        migration::fresh(db, &config.schema)
+            .await
+            .context("Failed to setup schema")?;
+        // recorded before the load so that --resume finds the settings
+        config
+            .meta()
+            .save_meta(db)
+            .await
+            .context("Failed to save meta")?;
+        println!("Finished setup schema.");
+    }
     // insert rows
     insert_commodity(db, &config)
         .await
@@ -45,55 +118,221 @@ pub async fn execute<T: Into<Config>>(db: &DatabaseConnection, config: T) -> Res
     insert_consumer(db, &config)
         .await
         .context("Failed to insert consumer")?;
-    println!("Finished insert commodity.");
+    println!("Finished insert consumer.");
+    let ids = load_ids(db, &config).await?;
+    insert_order(db, &config, ids)
+        .await
+        .context("Failed to insert order")?;
+    println!("Finished insert order.");
+    insert_evaluation(db, &config, ids)
+        .await
+        .context("Failed to insert evaluation")?;
+    println!("Finished insert evaluation.");
+    if config.schema.indexes == IndexMode::AfterLoad {
+        create_indexes(db)
+            .await
+            .context("Failed to create indexes")?;
+        println!("Finished create indexes.");
+    }
+    config
+        .meta()
+        .save_meta(db)
+        .await
+        .context("Failed to save meta")?;
+    println!("Finished save meta.");
+    Ok(())
+}
+
+impl Config {
+    fn meta(&self) -> meta::ActiveModel {
+        meta::ActiveModel::new(
+            self.scale_factor,
+            self.commodity_count,
+            self.consumer_count,
+            self.order_count,
+            self.evaluation_count,
+            self.seed,
+            self.schema.key_strategy,
+            self.schema.column_types,
+        )
+    }
+}
+
+/// Takes the seed, key strategy and column types from the meta table of the
+/// interrupted load, the rows it already wrote follow them.
+async fn load_recorded_settings(db: &DatabaseConnection, config: &mut Config) -> Result<()> {
+    let meta = meta::find(db)
+        .await
+        .context("Failed to read meta")?
+        .context("No meta found to resume from, prepare the dataset again")?;
+    let key_strategy = meta
+        .key_strategy()
+        .with_context(|| format!("Unknown key strategy {} in meta", meta.key_strategy))?;
+    let column_types = meta
+        .column_types()
+        .with_context(|| format!("Unknown column types {} in meta", meta.column_types))?;
+    if key_strategy != config.schema.key_strategy || column_types != config.schema.column_types {
+        println!(
+            "Resuming with the recorded --key-strategy {} and --column-types {}",
+            key_strategy.name(),
+            column_types.name()
+        );
+    }
+    config.seed = meta.seed();
+    config.schema.key_strategy = key_strategy;
+    config.schema.column_types = column_types;
+    Ok(())
+}
+
+/// Fails if any of the tables prepare would drop holds data, unless
+/// `--drop-existing` was given.
+async fn refuse_to_overwrite(db: &DatabaseConnection, config: &Config) -> Result<()> {
+    let non_empty: Vec<String> = entity::existing_tables(db)
+        .await
+        .context("Failed to look for existing tables")?
+        .into_iter()
+        .filter(|(table, rows)| *rows > 0 && table != entity::migration::Entity.table_name())
+        .map(|(table, rows)| format!("{} ({} rows)", table, rows))
+        .collect();
+    if non_empty.is_empty() {
+        return Ok(());
+    }
+    if !config.drop_existing {
+        bail!(
+            "Tables already contain data: {}. Pass --drop-existing to replace them or --resume to continue loading them.",
+            non_empty.join(", ")
+        );
+    }
+    println!("Dropping existing data: {}", non_empty.join(", "));
     Ok(())
 }
 
+/// Returns how many of the `count` rows of `entity` still need to be inserted.
+/// Without `--resume` the tables were just recreated, so nothing is counted.
+async fn missing_rows<E>(
+    db: &DatabaseConnection,
+    config: &Config,
+    entity: E,
+    count: u32,
+) -> Result<u32>
+where
+    E: EntityTrait,
+    E::Model: Sync,
+{
+    if !config.resume {
+        return Ok(count);
+    }
+    let existing = E::find().count(db).await? as u32;
+    println!(
+        "Found {} rows in {}, {} rows missing.",
+        existing,
+        entity.table_name(),
+        count.saturating_sub(existing)
+    );
+    Ok(count.saturating_sub(existing))
+}
+
 async fn insert_commodity(db: &DatabaseConnection, config: &Config) -> Result<()> {
-    batch_exec(
-        db,
-        config.commodity_count * 2,
-        config.txn_size,
-        config.concurrent,
-        |txn| {
-            Box::pin(async move {
-                let commodity_inserted =
-                    commodity::ActiveModel::rand_fake_new().insert(txn).await?;
-                let mut inventory_active = inventory::ActiveModel::rand_fake_new();
-                inventory_active.commodity_id = Set(commodity_inserted.id);
-                inventory_active.updated_at = Set(commodity_inserted.created_at.clone());
-                inventory_active.created_at = Set(commodity_inserted.created_at.clone());
-                inventory_active.insert(txn).await?;
-                Ok(2)
-            })
-        },
-    )
+    // commodity and inventory rows are inserted in the same transaction,
+    // so counting the commodity table is enough.
+    let missing = missing_rows(db, config, commodity::Entity, config.commodity_count).await?;
+    batch_exec(db, "commodity", missing, config, |txn| {
+        Box::pin(async move {
+            let commodity_inserted = commodity::ActiveModel::rand_fake_new().insert(txn).await?;
+            let mut inventory_active = inventory::ActiveModel::rand_fake_new();
+            inventory_active.commodity_id = Set(commodity_inserted.id);
+            inventory_active.updated_at = Set(commodity_inserted.created_at);
+            inventory_active.created_at = Set(commodity_inserted.created_at);
+            inventory_active.insert(txn).await?;
+            Ok(2)
+        })
+    })
     .await?;
     Ok(())
 }
 
 async fn insert_consumer(db: &DatabaseConnection, config: &Config) -> Result<()> {
-    batch_exec(
-        db,
-        config.consumer_count,
-        config.txn_size,
-        config.concurrent,
-        |txn| {
-            Box::pin(async move {
-                consumer::ActiveModel::rand_fake_new().insert(txn).await?;
-                Ok(1)
-            })
-        },
-    )
+    let missing = missing_rows(db, config, consumer::Entity, config.consumer_count).await?;
+    batch_exec(db, "consumer", missing, config, |txn| {
+        Box::pin(async move {
+            consumer::ActiveModel::rand_fake_new().insert(txn).await?;
+            Ok(1)
+        })
+    })
+    .await?;
+    Ok(())
+}
+
+/// Loads the keys of the consumers and commodities the historical rows reference.
+async fn load_ids(
+    db: &DatabaseConnection,
+    config: &Config,
+) -> Result<(&'static [Id], &'static [Id])> {
+    if config.order_count == 0 && config.evaluation_count == 0 {
+        return Ok((&[], &[]));
+    }
+    let consumer_ids = key::first_ids(db, consumer::Entity, config.consumer_count).await?;
+    let commodity_ids = key::first_ids(db, commodity::Entity, config.commodity_count).await?;
+    if consumer_ids.is_empty() || commodity_ids.is_empty() {
+        bail!("Orders and evaluations need at least one consumer and commodity");
+    }
+    Ok((consumer_ids, commodity_ids))
+}
+
+async fn insert_order(
+    db: &DatabaseConnection,
+    config: &Config,
+    (consumer_ids, commodity_ids): (&'static [Id], &'static [Id]),
+) -> Result<()> {
+    let missing = missing_rows(db, config, order::Entity, config.order_count).await?;
+    let history = chrono::Duration::days(config.history_days as i64);
+    batch_exec(db, "order", missing, config, move |txn| {
+        Box::pin(async move {
+            let mut order_active = order::ActiveModel::rand_fake_new(
+                rand_pick(consumer_ids),
+                rand_pick(commodity_ids),
+            );
+            order_active.created_at = Set(rand_history_datetime(history));
+            order_active.insert(txn).await?;
+            Ok(1)
+        })
+    })
     .await?;
     Ok(())
 }
 
+async fn insert_evaluation(
+    db: &DatabaseConnection,
+    config: &Config,
+    (consumer_ids, commodity_ids): (&'static [Id], &'static [Id]),
+) -> Result<()> {
+    let missing = missing_rows(db, config, evaluation::Entity, config.evaluation_count).await?;
+    let history = chrono::Duration::days(config.history_days as i64);
+    batch_exec(db, "evaluation", missing, config, move |txn| {
+        Box::pin(async move {
+            let mut evaluation_active = evaluation::ActiveModel::rand_fake_new(
+                rand_pick(consumer_ids),
+                rand_pick(commodity_ids),
+            );
+            let created_at = rand_history_datetime(history);
+            evaluation_active.updated_at = Set(created_at);
+            evaluation_active.created_at = Set(created_at);
+            evaluation_active.insert(txn).await?;
+            Ok(1)
+        })
+    })
+    .await?;
+    Ok(())
+}
+
+/// Calls `callback` `count` times for `table`, each call inserting one row and
+/// whatever depends on it, every transaction drawing from its own random
+/// stream so the same seed reproduces the same rows.
 async fn batch_exec<F>(
     db: &DatabaseConnection,
+    table: &'static str,
     count: u32,
-    txn_size_limit: u32,
-    concurrent: u32,
+    config: &Config,
     callback: F,
 ) -> Result<()>
 where
@@ -106,6 +345,7 @@ where
         + Copy
         + 'static,
 {
+    let (txn_size_limit, concurrent) = (config.txn_size, config.concurrent);
     let mut join_handle_vec = Vec::new();
     for i in 0..concurrent {
         let db = db.clone();
@@ -116,23 +356,24 @@ where
             }
             let mut rows = 0;
             let mut now = Instant::now();
+            let mut batch = 0;
             while unit_count > 0 {
                 let mut txn_size = txn_size_limit;
                 if unit_count < txn_size {
                     txn_size = unit_count;
                 }
                 unit_count -= txn_size;
-                let result = db
-                    .transaction::<_, u32, DbErr>(|txn| {
-                        Box::pin(async move {
-                            let mut rows = 0;
-                            while rows < txn_size {
-                                rows += callback(txn).await?;
-                            }
-                            Ok(rows)
-                        })
+                let transaction = db.transaction::<_, u32, DbErr>(|txn| {
+                    Box::pin(async move {
+                        let mut rows = 0;
+                        for _ in 0..txn_size {
+                            rows += callback(txn).await?;
+                        }
+                        Ok(rows)
                     })
-                    .await;
+                });
+                let result = rand::with_stream(rand::stream(table, i, batch), transaction).await;
+                batch += 1;
                 match result {
                     Ok(txn_rows) => rows += txn_rows,
                     Err(err) => return Err(err),
@@ -0,0 +1,308 @@
+use crate::rand::rand_i64;
+use anyhow::{bail, Context, Result};
+use chrono::Local;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::{sleep, sleep_until};
+
+/// Faults injected into the database traffic by a local TCP proxy.
+#[derive(clap::Args, Debug, Clone)]
+pub struct ProxyOptions {
+    /// Route every database URL through a local proxy injecting the faults below
+    #[clap(long)]
+    pub proxy: bool,
+    /// Milliseconds the proxy holds every chunk of data in each direction
+    #[clap(long, default_value = "0", requires = "proxy")]
+    pub proxy_latency: u64,
+    /// Milliseconds the latency varies by, up or down at random
+    #[clap(long, default_value = "0", requires = "proxy")]
+    pub proxy_jitter: u64,
+    /// KiB per second a connection carries in each direction, unlimited if omitted
+    #[clap(long, requires = "proxy")]
+    pub proxy_bandwidth: Option<u64>,
+    /// A fault SECONDS into the run, as AT:reset, AT:partition:SECONDS or AT:down:SECONDS,
+    /// limited to the N-th database URL with @N; repeat for several
+    #[clap(long = "proxy-fault", multiple_occurrences = true, requires = "proxy")]
+    pub proxy_faults: Vec<Fault>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FaultKind {
+    /// Reset the open connections
+    Reset,
+    /// Stop forwarding data, stalling the connections until it ends
+    Partition,
+    /// Reset the open connections and refuse new ones until it ends
+    Down,
+}
+
+/// `AT:KIND[:SECONDS][@ENDPOINT]`
+#[derive(Debug, Clone, Copy)]
+pub struct Fault {
+    at: Duration,
+    kind: FaultKind,
+    duration: Duration,
+    /// Index of the database URL, all if `None`.
+    endpoint: Option<usize>,
+}
+
+impl FromStr for Fault {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        const EXPECTED: &str = "expected AT:reset, AT:partition:SECONDS or AT:down:SECONDS, optionally followed by @ENDPOINT";
+        let (fault, endpoint) = match s.split_once('@') {
+            Some((fault, endpoint)) => match endpoint.trim().parse::<usize>() {
+                Ok(endpoint) if endpoint >= 1 => (fault, Some(endpoint - 1)),
+                _ => return Err(format!("invalid endpoint {:?}", endpoint)),
+            },
+            None => (s, None),
+        };
+        let seconds = |seconds: &str| {
+            seconds
+                .trim()
+                .parse::<f64>()
+                .ok()
+                .filter(|seconds| *seconds >= 0.0)
+                .map(Duration::from_secs_f64)
+                .ok_or_else(|| format!("invalid seconds {:?}", seconds))
+        };
+        let (at, kind, duration) = match fault.split(':').collect::<Vec<_>>()[..] {
+            [at, "reset"] => (seconds(at)?, FaultKind::Reset, Duration::ZERO),
+            [at, "partition", duration] => (seconds(at)?, FaultKind::Partition, seconds(duration)?),
+            [at, "down", duration] => (seconds(at)?, FaultKind::Down, seconds(duration)?),
+            _ => return Err(EXPECTED.to_owned()),
+        };
+        Ok(Fault {
+            at,
+            kind,
+            duration,
+            endpoint,
+        })
+    }
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            FaultKind::Reset => write!(f, "reset")?,
+            FaultKind::Partition => write!(f, "partition for {:?}", self.duration)?,
+            FaultKind::Down => write!(f, "down for {:?}", self.duration)?,
+        }
+        match self.endpoint {
+            Some(endpoint) => write!(f, " of endpoint #{}", endpoint + 1),
+            None => write!(f, " of all endpoints"),
+        }
+    }
+}
+
+impl Fault {
+    fn applies_to(&self, endpoint: usize) -> bool {
+        self.endpoint.is_none_or(|only| only == endpoint)
+    }
+
+    fn active(&self, endpoint: usize, kind: FaultKind, elapsed: Duration) -> bool {
+        self.kind == kind
+            && self.applies_to(endpoint)
+            && self.at <= elapsed
+            && elapsed < self.at + self.duration
+    }
+}
+
+/// How long the proxy sleeps at most before checking the faults again.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+struct Proxy {
+    options: ProxyOptions,
+    started: Instant,
+}
+
+impl Proxy {
+    fn active(&self, endpoint: usize, kind: FaultKind) -> bool {
+        let elapsed = self.started.elapsed();
+        self.options
+            .proxy_faults
+            .iter()
+            .any(|fault| fault.active(endpoint, kind, elapsed))
+    }
+
+    /// Resolves once a reset or down fault hits a connection opened `opened`
+    /// into the run.
+    async fn reset(&self, endpoint: usize, opened: Duration) {
+        loop {
+            let elapsed = self.started.elapsed();
+            if self.options.proxy_faults.iter().any(|fault| {
+                matches!(fault.kind, FaultKind::Reset | FaultKind::Down)
+                    && fault.applies_to(endpoint)
+                    && opened < fault.at
+                    && fault.at <= elapsed
+            }) {
+                return;
+            }
+            sleep(POLL_INTERVAL).await;
+        }
+    }
+
+    /// Holds `bytes` of data for the latency, the jitter and the bandwidth,
+    /// and for as long as the endpoint is partitioned.
+    async fn delay(&self, endpoint: usize, bytes: usize) {
+        while self.active(endpoint, FaultKind::Partition) {
+            sleep(POLL_INTERVAL).await;
+        }
+        let jitter = self.options.proxy_jitter as i64;
+        let mut delay = Duration::from_millis(
+            (self.options.proxy_latency as i64 + rand_i64(-jitter, jitter)).max(0) as u64,
+        );
+        if let Some(bandwidth) = self.options.proxy_bandwidth {
+            delay += Duration::from_secs_f64(bytes as f64 / (bandwidth.max(1) * 1024) as f64);
+        }
+        if !delay.is_zero() {
+            sleep(delay).await;
+        }
+    }
+}
+
+/// Starts a proxy for every URL and returns the URLs to connect to instead.
+pub async fn start(options: &ProxyOptions, db_urls: &[String]) -> Result<Vec<String>> {
+    let proxy = Arc::new(Proxy {
+        options: options.clone(),
+        started: Instant::now(),
+    });
+    let mut proxied_urls = Vec::new();
+    for (endpoint, db_url) in db_urls.iter().enumerate() {
+        let (upstream, rewrite) = upstream(db_url)?;
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .context("Failed to bind the proxy")?;
+        let local = listener.local_addr()?.to_string();
+        println!(
+            "Proxying endpoint #{} {} through {}",
+            endpoint + 1,
+            upstream,
+            local
+        );
+        tokio::spawn(accept(listener, upstream, endpoint, proxy.clone()));
+        proxied_urls.push(rewrite(&local));
+    }
+    println!(
+        "Injecting {}ms latency, {}ms jitter and {} bandwidth",
+        options.proxy_latency,
+        options.proxy_jitter,
+        options
+            .proxy_bandwidth
+            .map_or("unlimited".to_owned(), |bandwidth| format!(
+                "{} KiB/s",
+                bandwidth
+            ))
+    );
+    for fault in options.proxy_faults.iter().copied() {
+        let started = proxy.started;
+        tokio::spawn(async move {
+            sleep_until((started + fault.at).into()).await;
+            println!("{} [FAULT] {}", Local::now(), fault);
+            if fault.kind != FaultKind::Reset {
+                sleep(fault.duration).await;
+                println!("{} [FAULT] {} is over", Local::now(), fault);
+            }
+        });
+    }
+    Ok(proxied_urls)
+}
+
+/// The `host:port` a database URL points to and how to point it elsewhere.
+fn upstream(db_url: &str) -> Result<(String, impl Fn(&str) -> String + '_)> {
+    let (scheme, rest) = db_url
+        .split_once("://")
+        .with_context(|| format!("Can't proxy {}", db_url))?;
+    let default_port = match scheme {
+        "postgres" | "postgresql" => 5432,
+        "mysql" => 3306,
+        _ => bail!("The proxy needs a TCP database, not {}", scheme),
+    };
+    let authority_end = rest.find(['/', '?']).unwrap_or(rest.len());
+    let host_start = rest[..authority_end].rfind('@').map_or(0, |at| at + 1);
+    let host = &rest[host_start..authority_end];
+    let upstream = if host.contains(':') {
+        host.to_owned()
+    } else {
+        format!("{}:{}", host, default_port)
+    };
+    let prefix = &db_url[..db_url.len() - rest.len() + host_start];
+    let suffix = &rest[authority_end..];
+    Ok((upstream, move |local: &str| {
+        format!("{}{}{}", prefix, local, suffix)
+    }))
+}
+
+async fn accept(listener: TcpListener, upstream: String, endpoint: usize, proxy: Arc<Proxy>) {
+    loop {
+        let client = match listener.accept().await {
+            Ok((client, _)) => client,
+            Err(err) => {
+                println!("[WARN] The proxy failed to accept: {}", err);
+                continue;
+            }
+        };
+        if proxy.active(endpoint, FaultKind::Down) {
+            refuse(client);
+            continue;
+        }
+        let upstream = upstream.clone();
+        let proxy = proxy.clone();
+        tokio::spawn(async move {
+            match TcpStream::connect(&upstream).await {
+                Ok(server) => forward(client, server, endpoint, proxy).await,
+                Err(_) => refuse(client),
+            }
+        });
+    }
+}
+
+/// Closes `stream` with a reset instead of the orderly shutdown.
+fn refuse(stream: TcpStream) {
+    let _ = stream.set_linger(Some(Duration::ZERO));
+}
+
+async fn forward(mut client: TcpStream, mut server: TcpStream, endpoint: usize, proxy: Arc<Proxy>) {
+    let opened = proxy.started.elapsed();
+    let reset = {
+        let (mut client_read, mut client_write) = client.split();
+        let (mut server_read, mut server_write) = server.split();
+        tokio::select! {
+            _ = async {
+                tokio::join!(
+                    pipe(&mut client_read, &mut server_write, endpoint, &proxy),
+                    pipe(&mut server_read, &mut client_write, endpoint, &proxy)
+                )
+            } => false,
+            _ = proxy.reset(endpoint, opened) => true,
+        }
+    };
+    if reset {
+        refuse(client);
+        refuse(server);
+    }
+}
+
+async fn pipe<R, W>(from: &mut R, to: &mut W, endpoint: usize, proxy: &Proxy)
+where
+    R: AsyncReadExt + Unpin,
+    W: AsyncWriteExt + Unpin,
+{
+    let mut buf = vec![0; 16 * 1024];
+    loop {
+        let bytes = match from.read(&mut buf).await {
+            Ok(0) | Err(_) => break,
+            Ok(bytes) => bytes,
+        };
+        proxy.delay(endpoint, bytes).await;
+        if to.write_all(&buf[..bytes]).await.is_err() {
+            break;
+        }
+    }
+    let _ = to.shutdown().await;
+}
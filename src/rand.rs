@@ -1,6 +1,77 @@
+use crate::entity::types::Timestamp;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::cell::RefCell;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+static SEED: AtomicU64 = AtomicU64::new(0);
+
+thread_local! {
+    // Draws outside of a stream, e.g. the workload of run, don't follow the seed.
+    static RNG: RefCell<StdRng> = RefCell::new(StdRng::from_entropy());
+}
+
+tokio::task_local! {
+    static STREAM: RefCell<StdRng>;
+}
+
+/// Sets the seed the streams are derived from.
+/// Only the numbers produced here follow the seed, the fake texts don't.
+pub fn set_seed(seed: u64) {
+    SEED.store(seed, Ordering::Relaxed);
+}
+
+/// Identifies the stream of one unit of work, the `batch`-th one of `worker`
+/// filling `table`, independent of the thread or task that happens to run it.
+pub fn stream(table: &str, worker: u32, batch: u32) -> u64 {
+    // FNV-1a, stable across builds unlike the std hasher
+    let table = table.bytes().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01b3)
+    });
+    table ^ ((worker as u64) << 32 | batch as u64)
+}
+
+fn stream_rng(stream: u64) -> RefCell<StdRng> {
+    let seed = SEED.load(Ordering::Relaxed);
+    RefCell::new(StdRng::seed_from_u64(
+        seed ^ stream.wrapping_mul(0x9e37_79b9_7f4a_7c15),
+    ))
+}
+
+/// Runs `f` with every number it draws taken from `stream` of the seed.
+pub async fn with_stream<F: Future>(stream: u64, f: F) -> F::Output {
+    STREAM.scope(stream_rng(stream), f).await
+}
+
+/// Like `with_stream`, for a unit of work running on a thread of its own.
+pub fn with_stream_sync<R>(stream: u64, f: impl FnOnce() -> R) -> R {
+    STREAM.sync_scope(stream_rng(stream), f)
+}
+
+fn with_rng<R>(f: impl FnOnce(&mut StdRng) -> R) -> R {
+    if STREAM.try_with(|_| ()).is_ok() {
+        STREAM.with(|rng| f(&mut rng.borrow_mut()))
+    } else {
+        RNG.with(|rng| f(&mut rng.borrow_mut()))
+    }
+}
+
 pub fn rand_i64(start: i64, end: i64) -> i64 {
-    let i: u32 = rand::random();
-    let mut i = i as i64;
-    i = start + (i % (end - start + 1));
-    return i;
+    with_rng(|rng| rng.gen_range(start..=end))
+}
+
+/// Returns a number in `[0, 1)`.
+pub fn rand_f64() -> f64 {
+    with_rng(|rng| rng.gen())
+}
+
+/// Picks one of `items` at random, which must not be empty.
+pub fn rand_pick<T: Copy>(items: &[T]) -> T {
+    items[rand_i64(0, items.len() as i64 - 1) as usize]
+}
+
+/// Returns a random point in time within `history` before now.
+pub fn rand_history_datetime(history: chrono::Duration) -> Timestamp {
+    let offset = rand_i64(0, history.num_seconds());
+    Timestamp::now() - chrono::Duration::seconds(offset)
 }
@@ -0,0 +1,160 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::time::{sleep, sleep_until};
+
+/// What `--rate-limit` counts.
+#[derive(clap::ValueEnum, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateUnit {
+    /// Transactions per second, whatever they change
+    Txn,
+    /// Rows changed per second, a transaction that changed none counts as one
+    Row,
+}
+
+/// A token bucket refilled at `rate` tokens per second, holding at most
+/// `burst`. The rate can change while takers wait, so they poll the bucket
+/// instead of sleeping until a precomputed time.
+pub struct TokenBucket {
+    burst: f64,
+    state: Mutex<BucketState>,
+}
+
+struct BucketState {
+    rate: f64,
+    /// Negative after a take larger than the burst, which is paid off first.
+    tokens: f64,
+    updated: Instant,
+}
+
+/// How long a taker sleeps at most before checking the bucket again.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+impl BucketState {
+    fn refill(&mut self, burst: f64) {
+        let now = Instant::now();
+        let refill = now.duration_since(self.updated).as_secs_f64() * self.rate;
+        self.tokens = (self.tokens + refill).min(burst);
+        self.updated = now;
+    }
+}
+
+impl TokenBucket {
+    /// Starts with a full bucket.
+    pub fn new(rate: f64, burst: f64) -> Self {
+        Self {
+            burst,
+            state: Mutex::new(BucketState {
+                rate,
+                tokens: burst,
+                updated: Instant::now(),
+            }),
+        }
+    }
+
+    /// Changes the refill rate from now on.
+    pub fn set_rate(&self, rate: f64) {
+        let mut state = self.state.lock().expect("token bucket poisoned");
+        state.refill(self.burst);
+        state.rate = rate;
+    }
+
+    /// Takes `tokens` out of the bucket if it holds them, or as much as the
+    /// burst for larger takes. Otherwise returns how long to wait for them.
+    fn try_take(&self, tokens: f64) -> Option<Duration> {
+        let mut state = self.state.lock().expect("token bucket poisoned");
+        state.refill(self.burst);
+        let needed = tokens.min(self.burst);
+        if state.tokens >= needed {
+            state.tokens -= tokens;
+            return None;
+        }
+        if state.rate <= 0.0 {
+            return Some(POLL_INTERVAL);
+        }
+        Some(Duration::from_secs_f64((needed - state.tokens) / state.rate).min(POLL_INTERVAL))
+    }
+
+    /// Takes `tokens` out of the bucket, waiting for them if it runs dry.
+    pub async fn take(&self, tokens: f64) {
+        while let Some(wait) = self.try_take(tokens) {
+            sleep(wait).await;
+        }
+    }
+}
+
+/// Start times of an open-loop run, `rate` per second whether or not the
+/// workers keep up, so a stall shows up as transactions starting late.
+pub struct Arrivals {
+    state: Mutex<ArrivalState>,
+}
+
+struct ArrivalState {
+    rate: f64,
+    /// The start time the next taker is given.
+    next: Instant,
+}
+
+impl Arrivals {
+    pub fn new(rate: f64) -> Self {
+        Self {
+            state: Mutex::new(ArrivalState {
+                rate,
+                next: Instant::now(),
+            }),
+        }
+    }
+
+    pub fn set_rate(&self, rate: f64) {
+        self.state.lock().expect("arrivals poisoned").rate = rate;
+    }
+
+    /// Claims the next start time once it is at most a poll interval away,
+    /// so a rate change applies to the start times after it. Otherwise
+    /// returns how long to wait before trying again.
+    fn try_claim(&self) -> Result<Instant, Duration> {
+        let mut state = self.state.lock().expect("arrivals poisoned");
+        let now = Instant::now();
+        if state.rate <= 0.0 {
+            // nothing is owed for the time without arrivals
+            state.next = state.next.max(now);
+            return Err(POLL_INTERVAL);
+        }
+        if state.next > now + POLL_INTERVAL {
+            return Err((state.next - now - POLL_INTERVAL).min(POLL_INTERVAL));
+        }
+        let intended = state.next;
+        let interval = Duration::from_secs_f64(1.0 / state.rate);
+        state.next += interval;
+        Ok(intended)
+    }
+
+    /// Waits for the next start time and returns it, right away if the
+    /// workers are behind.
+    pub async fn next(&self) -> Instant {
+        let intended = loop {
+            match self.try_claim() {
+                Ok(intended) => break intended,
+                Err(wait) => sleep(wait).await,
+            }
+        };
+        sleep_until(intended.into()).await;
+        intended
+    }
+}
+
+/// Paces the workers of `run`.
+pub enum Limiter {
+    /// Closed-loop, a worker waits for a token before every transaction
+    Bucket(TokenBucket),
+    /// Open-loop, a worker runs every transaction at its intended start time
+    OpenLoop(Arrivals),
+}
+
+impl Limiter {
+    pub fn set_rate(&self, rate: f64) {
+        match self {
+            Limiter::Bucket(bucket) => bucket.set_rate(rate),
+            Limiter::OpenLoop(arrivals) => arrivals.set_rate(rate),
+        }
+    }
+}
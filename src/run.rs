@@ -1,217 +1,934 @@
 use crate::{
-    entity::{commodity, consumer, evaluation, inventory, order},
-    rand::rand_i64,
+    availability::Availability,
+    dialect,
+    entity::{
+        archive::{self, ArchiveMode},
+        commodity, consumer, evaluation, inventory,
+        key::{self, Id, KeyStrategy},
+        meta, order, partition,
+        types::{self, ColumnTypes, Price, Timestamp},
+    },
+    migration::SCHEMA_VERSION,
+    pool::{endpoint_name, Balance},
+    rand::{rand_f64, rand_i64, rand_pick},
+    rate_limit::{Arrivals, Limiter, RateUnit, TokenBucket},
+    schedule::LoadSchedule,
+    session::{SessionConfig, ThinkTime},
 };
-use anyhow::Result;
+use anyhow::{bail, Context, Result};
 use chrono::Local;
 use flume::{Receiver, Sender};
 use futures::{future::join_all, Future};
 use sea_orm::{
-    ActiveModelTrait, DatabaseConnection, DatabaseTransaction, DbErr, EntityTrait, Set,
-    TransactionTrait,
+    ActiveModelTrait, ConnectionTrait, DatabaseConnection, DatabaseTransaction, DbErr, EntityTrait,
+    Set, TransactionTrait,
 };
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock};
 use std::time::Instant;
 use std::{pin::Pin, time::Duration};
 use tokio::time::sleep;
 
+/// The workers of one service and, if it doesn't share the limiter of the
+/// others, its own rate.
 #[derive(Debug, Clone, Copy)]
-pub struct Config {
-    commodity_count: u32,
-    consumer_count: u32,
+struct ServiceConfig {
+    name: &'static str,
     concurrent: u32,
+    rate_limit: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Config {
+    commodity_ids: &'static [Id],
+    consumer_ids: &'static [Id],
+    orders: ServiceConfig,
+    evaluation: ServiceConfig,
+    change_price: ServiceConfig,
+    /// Replaces the services when set.
+    session: Option<SessionConfig>,
+    balance: Balance,
+    resilient: bool,
     downgrade: bool,
-    rate_limit: u32,
+    /// `None` without a schedule runs closed-loop as fast as the database allows.
+    rate_limit: Option<f64>,
+    schedule: Option<&'static LoadSchedule>,
+    burst: f64,
+    rate_unit: RateUnit,
+    open_loop: bool,
+    partition_maintenance_interval: Option<u64>,
+    partitions_ahead: u32,
+    partition_retention_days: Option<u32>,
+    archive_retention_days: Option<u32>,
+    archive_mode: ArchiveMode,
+    archive_batch_size: u64,
+    archive_rate: u32,
 }
 
-impl From<&super::Args> for Config {
-    fn from(args: &super::Args) -> Self {
-        let (commodity_count, consumer_count, downgrade, rate_limit) = match args.command {
-            crate::SubCommandArgs::Prepare { .. } => unreachable!(),
+impl Config {
+    /// Builds the config from the command line, filling in the table sizes
+    /// that were not given from the metadata recorded by prepare.
+    async fn load(db: &DatabaseConnection, args: &super::Args) -> Result<Self> {
+        let (commodity_count, consumer_count) = match args.command {
             crate::SubCommandArgs::Run {
                 commodity_count,
                 consumer_count,
+                ..
+            } => (commodity_count, consumer_count),
+            _ => unreachable!(),
+        };
+        if let Some(version) = meta::find_version(db)
+            .await
+            .ok()
+            .flatten()
+            .filter(|version| !version.is_compatible())
+        {
+            bail!(
+                "The dataset was prepared by tiny_orders {} with schema version {}, \
+                 but this build expects schema version {}. Please prepare again.",
+                version.tool_version,
+                version.schema_version,
+                SCHEMA_VERSION
+            );
+        }
+        let (commodity_count, consumer_count, key_strategy, column_types) =
+            match meta::find(db).await {
+                Ok(Some(meta)) => {
+                    let prepared = (meta.commodity_count as u32, meta.consumer_count as u32);
+                    let counts = (
+                        commodity_count.unwrap_or(prepared.0),
+                        consumer_count.unwrap_or(prepared.1),
+                    );
+                    if counts.0 > prepared.0 || counts.1 > prepared.1 {
+                        bail!(
+                            "Only {} commodities and {} consumers were prepared",
+                            prepared.0,
+                            prepared.1
+                        );
+                    }
+                    let key_strategy = meta.key_strategy().with_context(|| {
+                        format!("Unknown key strategy {} in meta", meta.key_strategy)
+                    })?;
+                    let column_types = meta.column_types().with_context(|| {
+                        format!("Unknown column types {} in meta", meta.column_types)
+                    })?;
+                    (counts.0, counts.1, key_strategy, column_types)
+                }
+                meta => match (commodity_count, consumer_count) {
+                    (Some(commodity_count), Some(consumer_count)) => {
+                        println!("[WARN] No meta found, trust the given counts.");
+                        (
+                            commodity_count,
+                            consumer_count,
+                            KeyStrategy::AutoIncrement,
+                            ColumnTypes::Typed,
+                        )
+                    }
+                    _ => {
+                        meta.context("Failed to read meta")?;
+                        bail!("No meta found, pass --commodity-count and --consumer-count")
+                    }
+                },
+            };
+        key::set_strategy(key_strategy);
+        types::set_column_types(column_types);
+        let commodity_ids = key::first_ids(db, commodity::Entity, commodity_count).await?;
+        let consumer_ids = key::first_ids(db, consumer::Entity, consumer_count).await?;
+        if commodity_ids.is_empty() || consumer_ids.is_empty() {
+            bail!("No commodities or consumers found, please prepare first");
+        }
+        match args.command {
+            crate::SubCommandArgs::Run {
                 downgrade,
                 rate_limit,
-            } => (commodity_count, consumer_count, downgrade, rate_limit),
-        };
-        Self {
-            commodity_count,
-            consumer_count,
-            concurrent: args.concurrent,
-            downgrade,
-            rate_limit,
+                ramp,
+                step,
+                ref schedule_file,
+                burst,
+                rate_unit,
+                open_loop,
+                orders_concurrency,
+                evaluation_concurrency,
+                change_price_concurrency,
+                orders_rate,
+                evaluation_rate,
+                change_price_rate,
+                resilient,
+                sessions,
+                think_time,
+                browse_count,
+                evaluation_probability,
+                partition_maintenance_interval,
+                partitions_ahead,
+                partition_retention_days,
+                archive_retention_days,
+                archive_mode,
+                archive_batch_size,
+                archive_rate,
+                ..
+            } => {
+                if rate_limit.is_some_and(|rate_limit| rate_limit <= 0.0) || burst < 1.0 {
+                    bail!("--rate-limit must be positive and --burst at least 1");
+                }
+                if [orders_rate, evaluation_rate, change_price_rate]
+                    .iter()
+                    .any(|rate| rate.is_some_and(|rate| rate <= 0.0))
+                {
+                    bail!("The rates of the services must be positive");
+                }
+                if sessions.is_none()
+                    && (think_time.is_some()
+                        || browse_count.is_some()
+                        || evaluation_probability.is_some())
+                {
+                    bail!(
+                        "--think-time, --browse-count and --evaluation-probability need --sessions"
+                    );
+                }
+                let evaluation_probability = evaluation_probability.unwrap_or(0.3);
+                if !(0.0..=1.0).contains(&evaluation_probability) {
+                    bail!("--evaluation-probability must be between 0 and 1");
+                }
+                let service = |name, concurrent: Option<u32>, rate_limit| ServiceConfig {
+                    name,
+                    concurrent: concurrent.unwrap_or(args.concurrent),
+                    rate_limit,
+                };
+                if open_loop && rate_unit == RateUnit::Row {
+                    bail!(
+                        "--open-loop paces transactions, the rows aren't known before they start"
+                    );
+                }
+                let schedule = match (ramp, step, schedule_file) {
+                    (Some(ramp), _, _) => Some(LoadSchedule::Ramp(ramp)),
+                    (_, Some(step), _) => Some(LoadSchedule::Step(step)),
+                    (_, _, Some(schedule_file)) => Some(LoadSchedule::from_file(schedule_file)?),
+                    _ => None,
+                };
+                Ok(Self {
+                    commodity_ids,
+                    consumer_ids,
+                    orders: service("orders", orders_concurrency, orders_rate),
+                    evaluation: service("evaluation", evaluation_concurrency, evaluation_rate),
+                    change_price: service(
+                        "change price",
+                        change_price_concurrency,
+                        change_price_rate,
+                    ),
+                    session: sessions.map(|users| SessionConfig {
+                        users,
+                        think_time: think_time.unwrap_or(ThinkTime::Exponential(1000)),
+                        browse_count: browse_count.unwrap_or_default(),
+                        evaluation_probability,
+                    }),
+                    balance: args.pool.balance,
+                    resilient,
+                    downgrade,
+                    rate_limit,
+                    // leaked so the config stays `Copy`
+                    schedule: schedule.map(|schedule| &*Box::leak(Box::new(schedule))),
+                    burst,
+                    rate_unit,
+                    open_loop,
+                    partition_maintenance_interval,
+                    partitions_ahead,
+                    partition_retention_days,
+                    archive_retention_days,
+                    archive_mode,
+                    archive_batch_size,
+                    archive_rate,
+                })
+            }
+            _ => unreachable!(),
         }
     }
 }
 
-const TOKEN_NUMBER_PRE_SECOND: u32 = 50;
+/// Set by Ctrl-C, the workers finish their transaction and stop.
+static STOPPED: AtomicBool = AtomicBool::new(false);
 
-pub async fn execute<T: Into<Config>>(db: &DatabaseConnection, config: T) -> Result<()> {
-    let config = config.into();
-    let (token_tx, token_rx) = flume::bounded(10);
+/// Resolves once Ctrl-C was pressed, to cut short the wait for the rate limiter.
+async fn stopped() {
+    while !STOPPED.load(Ordering::Relaxed) {
+        sleep(Duration::from_millis(50)).await;
+    }
+}
+
+/// Transactions that failed on a conflict and were run again.
+static RETRIES: AtomicU64 = AtomicU64::new(0);
+static ARCHIVE_RETRIES: AtomicU64 = AtomicU64::new(0);
+
+/// Set in resilient runs, which ride out lost connections.
+static AVAILABILITY: OnceLock<Availability> = OnceLock::new();
+
+/// How long a worker waits before retrying after losing its connection.
+const RECONNECT_BACKOFF: Duration = Duration::from_millis(100);
+
+/// The endpoint the next worker gets when they are handed out in turn.
+static NEXT_ENDPOINT: AtomicUsize = AtomicUsize::new(0);
+
+/// Runs the workload against all of `dbs`, one per database URL. The
+/// background services only use the first.
+pub async fn execute(dbs: &[DatabaseConnection], args: &super::Args) -> Result<()> {
+    let db = &dbs[0];
+    if dbs
+        .iter()
+        .any(|other| other.get_database_backend() != db.get_database_backend())
+    {
+        bail!("All database URLs must point to the same kind of database");
+    }
+    let config = Config::load(db, args).await?;
+    let server = dialect::server();
+    println!(
+        "Running on {} ({}) against {} commodities and {} consumers",
+        server.dialect,
+        server.version,
+        config.commodity_ids.len(),
+        config.consumer_ids.len()
+    );
+    if dbs.len() > 1 {
+        for (endpoint, url) in args.db_url.iter().enumerate() {
+            println!("Endpoint #{}: {}", endpoint + 1, endpoint_name(url));
+        }
+        println!("Balancing the workers {}", config.balance.name());
+    }
+    let workers = match (config.session, config.downgrade) {
+        (Some(session), _) => session.users,
+        (None, true) => config.evaluation.concurrent,
+        (None, false) => {
+            config.orders.concurrent + config.evaluation.concurrent + config.change_price.concurrent
+        }
+    } + config.archive_retention_days.is_some() as u32;
+    // every endpoint has a pool of its own
+    let connections = args.pool.max_connections(db.get_database_backend()) * dbs.len() as u32;
+    if workers > connections {
+        println!(
+            "[WARN] {} workers share {} pooled connections and wait for each other, see --max-connections",
+            workers, connections
+        );
+    }
+    let maintenance_interval = match config.partition_maintenance_interval {
+        Some(interval) => Some(interval),
+        // the partitions created ahead run out after a few days otherwise
+        None if partition::is_range_partitioned(db).await? => {
+            Some(DEFAULT_PARTITION_MAINTENANCE_INTERVAL)
+        }
+        None => None,
+    };
+    if let Some(interval) = maintenance_interval {
+        println!("Maintaining the daily partitions every {}s", interval);
+        partition_maintenance(db, interval, config);
+    }
+    if let Some(retention_days) = config.archive_retention_days {
+        archive_service(db, retention_days, config).await?;
+    }
+    ctrlc::set_handler(|| {
+        println!("receive the exit signal, exit...");
+        STOPPED.store(true, Ordering::Relaxed);
+    })
+    .expect("Error setting Ctrl-C handler");
+    if config.resilient {
+        println!(
+            "Retrying lost connections every {}ms",
+            RECONNECT_BACKOFF.as_millis()
+        );
+        let _ = AVAILABILITY.set(Availability::new(dbs.len()));
+    }
+    if let Some(session) = config.session {
+        println!(
+            "Running {} sessions, browsing {} commodities with {} think time",
+            session.users, session.browse_count, session.think_time
+        );
+        let (martix_tx, martix_rx) = flume::unbounded();
+        let (sessions, _) = tokio::join!(
+            session_service(dbs, martix_tx, config, session),
+            martix_service(martix_rx, "Step", &RETRIES, None, dbs.len())
+        );
+        sessions?;
+        report_availability();
+        return Ok(());
+    }
+    let rate_unit = match config.rate_unit {
+        RateUnit::Txn => "Txn",
+        RateUnit::Row => "Row",
+    };
+    // one limiter shared by all services
+    let started = Instant::now();
+    let new_limiter = |rate: f64| {
+        Arc::new(if config.open_loop {
+            Limiter::OpenLoop(Arrivals::new(rate))
+        } else {
+            Limiter::Bucket(TokenBucket::new(rate, config.burst))
+        })
+    };
+    let pacing = if config.open_loop {
+        "open-loop".to_owned()
+    } else {
+        format!("with bursts of {}", config.burst)
+    };
+    let limiter = match (config.rate_limit, config.schedule) {
+        (Some(rate_limit), _) => {
+            println!("Limiting to {} {}/s {}", rate_limit, rate_unit, pacing);
+            Some(new_limiter(rate_limit))
+        }
+        (None, Some(schedule)) => {
+            println!("Following a {} schedule {}", rate_unit, pacing);
+            let limiter = new_limiter(schedule.rate_at(Duration::ZERO));
+            drive_schedule(limiter.clone(), schedule, started);
+            Some(limiter)
+        }
+        (None, None) => {
+            let services = [config.orders, config.evaluation, config.change_price];
+            if services.iter().any(|service| service.rate_limit.is_some()) {
+                println!("Running unlimited apart from the services with a rate of their own");
+            } else {
+                println!("Running unlimited");
+            }
+            None
+        }
+    };
+    // a service with a rate of its own gets its own token stream
+    let limiter_of = |service: ServiceConfig| {
+        println!("{} workers for {}", service.concurrent, service.name);
+        match service.rate_limit {
+            Some(rate_limit) => {
+                println!(
+                    "Limiting {} to {} {}/s {}",
+                    service.name, rate_limit, rate_unit, pacing
+                );
+                Some(new_limiter(rate_limit))
+            }
+            None => limiter.clone(),
+        }
+    };
     let (martix_tx, martix_rx) = flume::unbounded();
-    let token_generator_handle = token_generator(token_tx, config.rate_limit);
-    let evaluation_service_handle =
-        evaluation_service(db, token_rx.clone(), martix_tx.clone(), config);
-    let martix_service_handle = martix_service(martix_rx);
+    let martix_service_handle = martix_service(
+        martix_rx,
+        "Txn",
+        &RETRIES,
+        config.schedule.map(|schedule| (schedule, started)),
+        dbs.len(),
+    );
     if config.downgrade {
         println!("Running with downgrade mode");
-        std::mem::drop(martix_tx);
-        tokio::join!(
-            token_generator_handle,
-            evaluation_service_handle,
-            martix_service_handle
-        )
-        .1?;
+        let evaluation_service_handle =
+            evaluation_service(dbs, limiter_of(config.evaluation), martix_tx, config);
+        tokio::join!(evaluation_service_handle, martix_service_handle).0?;
+        report_availability();
         return Ok(());
     }
     println!("Running with normal mode");
-    let orders_service_handle = orders_service(db, token_rx.clone(), martix_tx.clone(), config);
-    let change_price_service_handle = change_price_service(db, token_rx, martix_tx, config);
-    tokio::join!(
-        token_generator_handle,
+    let orders_service_handle =
+        orders_service(dbs, limiter_of(config.orders), martix_tx.clone(), config);
+    let evaluation_service_handle = evaluation_service(
+        dbs,
+        limiter_of(config.evaluation),
+        martix_tx.clone(),
+        config,
+    );
+    let change_price_service_handle =
+        change_price_service(dbs, limiter_of(config.change_price), martix_tx, config);
+    let (orders, evaluation, change_price, _) = tokio::join!(
         orders_service_handle,
         evaluation_service_handle,
         change_price_service_handle,
         martix_service_handle
-    )
-    .1?;
+    );
+    orders?;
+    evaluation?;
+    change_price?;
+    report_availability();
     Ok(())
 }
 
-async fn token_generator(token_tx: Sender<u32>, rate_limit: u32) {
-    let (exit_tx, exit_rx) = flume::bounded(1);
-    ctrlc::set_handler(move || {
-        exit_tx.send(()).expect("Failed to send exit signal");
-    })
-    .expect("Error setting Ctrl-C handler");
-    let mut rate_unit = rate_limit / TOKEN_NUMBER_PRE_SECOND;
-    if rate_unit == 0 {
-        rate_unit += 1;
+/// Records a transaction that lost its connection in a resilient run, false
+/// if the run isn't resilient or the transaction failed otherwise.
+fn lost_connection(endpoint: usize, err: &DbErr, attempted: Instant) -> bool {
+    match AVAILABILITY.get() {
+        Some(availability) if dialect::dialect().is_connection_lost(err) => {
+            availability.failed(endpoint, err, attempted);
+            true
+        }
+        _ => false,
     }
+}
+
+fn committed(endpoint: usize) {
+    if let Some(availability) = AVAILABILITY.get() {
+        availability.committed(endpoint);
+    }
+}
+
+fn report_availability() {
+    if let Some(availability) = AVAILABILITY.get() {
+        availability.report();
+    }
+}
+
+/// Sets the rate of `limiter` to the one `schedule` asks for, announcing every step.
+fn drive_schedule(limiter: Arc<Limiter>, schedule: &'static LoadSchedule, started: Instant) {
+    tokio::spawn(async move {
+        let mut current_step = None;
+        while !STOPPED.load(Ordering::Relaxed) {
+            let elapsed = started.elapsed();
+            limiter.set_rate(schedule.rate_at(elapsed));
+            let step = schedule.step_at(elapsed);
+            if current_step != Some(step) {
+                println!("Step {}: {}", step + 1, schedule.describe_step(step));
+                current_step = Some(step);
+            }
+            sleep(Duration::from_millis(100)).await;
+        }
+    });
+}
+
+/// Seconds between partition maintenance runs on range partitioned tables
+/// when `--partition-maintenance-interval` is omitted.
+const DEFAULT_PARTITION_MAINTENANCE_INTERVAL: u64 = 3600;
+
+/// Keeps the daily partitions rolling in the background while the workload runs.
+fn partition_maintenance(db: &DatabaseConnection, interval: u64, config: Config) {
+    let db = db.clone();
     tokio::spawn(async move {
         loop {
-            if let Ok(_) = exit_rx.try_recv() {
-                println!("receive the exit signal, exit...");
-                return;
+            if let Err(err) = partition::maintain(
+                &db,
+                config.partitions_ahead,
+                config.partition_retention_days,
+            )
+            .await
+            {
+                println!("[WARN] Partition maintenance failed: {}", err);
             }
-            token_tx.send_timeout(rate_unit, Duration::from_millis(50));
-            sleep(Duration::from_millis(1000 / TOKEN_NUMBER_PRE_SECOND as u64)).await;
+            sleep(Duration::from_secs(interval)).await;
         }
     });
 }
 
-async fn orders_service(
+/// Archives the orders and evaluations past the retention window in the
+/// background, one batch per table and transaction at `--archive-rate`,
+/// reporting its latency on a line of its own.
+async fn archive_service(
     db: &DatabaseConnection,
-    token_rx: Receiver<u32>,
-    martix_tx: Sender<(u32, u32)>,
+    retention_days: u32,
     config: Config,
 ) -> Result<()> {
-    run_service(db, token_rx, martix_tx, config.concurrent, move |txn| {
-        Box::pin(async move {
-            let consumer_id = rand_i64(1, config.consumer_count as i64);
-            let commodity_id = rand_i64(1, config.commodity_count as i64);
-            let commodity = match commodity::Entity::find_by_id(commodity_id).one(txn).await? {
-                Some(e) => e,
-                None => {
-                    println!(
-                        "[WARN] Can't find the commodity({}), retrying.",
-                        commodity_id
-                    );
-                    return Ok(0);
+    if config.archive_mode == ArchiveMode::Move {
+        archive::create_archive_table(db, order::Entity)
+            .await
+            .context("Failed to create the order archive")?;
+        archive::create_archive_table(db, evaluation::Entity)
+            .await
+            .context("Failed to create the evaluation archive")?;
+    }
+    println!(
+        "Archiving rows older than {} days, {} rows per batch, {} batches/s",
+        retention_days, config.archive_batch_size, config.archive_rate
+    );
+    let (martix_tx, martix_rx) = flume::unbounded();
+    tokio::spawn(martix_service(
+        martix_rx,
+        "Archive",
+        &ARCHIVE_RETRIES,
+        None,
+        1,
+    ));
+    let db = db.clone();
+    let dialect = dialect::dialect();
+    let interval = Duration::from_secs(1) / config.archive_rate.max(1);
+    tokio::spawn(async move {
+        loop {
+            let cutoff = Timestamp::now() - chrono::Duration::days(retention_days as i64);
+            let now = Instant::now();
+            let result = timed_transaction(&db, |txn| {
+                Box::pin(async move {
+                    let orders = archive::archive_batch(
+                        txn,
+                        order::Entity,
+                        order::Column::CreatedAt,
+                        cutoff,
+                        config.archive_batch_size,
+                        config.archive_mode,
+                    )
+                    .await?;
+                    let evaluations = archive::archive_batch(
+                        txn,
+                        evaluation::Entity,
+                        evaluation::Column::CreatedAt,
+                        cutoff,
+                        config.archive_batch_size,
+                        config.archive_mode,
+                    )
+                    .await?;
+                    Ok(orders + evaluations)
+                })
+            })
+            .await;
+            match result {
+                Ok((archived, pool_wait)) => {
+                    let _ = martix_tx.send(Sample::new(0, archived, now, pool_wait, None));
                 }
-            };
-            let inventory = inventory::Entity::find_by_id(commodity_id)
-                .one(txn)
-                .await?
-                .expect("Can't find the inventory");
-            if inventory.inventory <= 0 {
-                return Ok(0);
-            }
-            consumer::Entity::find_by_id(consumer_id)
-                .one(txn)
-                .await?
-                .expect("Can't find the consumer");
-            let inventory_number = inventory.inventory;
-            let mut sold_number = rand_i64(1, 5);
-            if inventory_number < sold_number {
-                sold_number = inventory_number;
+                Err(err) if dialect.is_retryable(&err) => {
+                    ARCHIVE_RETRIES.fetch_add(1, Ordering::Relaxed);
+                }
+                Err(err) => println!("[WARN] Archiving failed: {}", err),
             }
-            let mut inventory_active: inventory::ActiveModel = inventory.into();
-            inventory_active.updated_at = Set(Local::now().naive_local());
-            inventory_active.inventory = Set(inventory_number - sold_number);
-            inventory_active.update(txn).await?;
-
-            let mut order_active = order::ActiveModel::new();
-            order_active.consumer_id = Set(consumer_id);
-            order_active.commodity_id = Set(commodity_id);
-            order_active.sold_uint_price = Set(commodity.price);
-            order_active.sold_number = Set(sold_number);
-            order_active.insert(txn).await?;
-            Ok(2)
-        })
+            sleep(interval.saturating_sub(now.elapsed())).await;
+        }
+    });
+    Ok(())
+}
+
+async fn orders_service(
+    dbs: &[DatabaseConnection],
+    limiter: Option<Arc<Limiter>>,
+    martix_tx: Sender<Sample>,
+    config: Config,
+) -> Result<()> {
+    run_service(dbs, limiter, martix_tx, config, config.orders, move |txn| {
+        Box::pin(place_order(
+            txn,
+            rand_pick(config.consumer_ids),
+            rand_pick(config.commodity_ids),
+        ))
     })
     .await?;
     Ok(())
 }
 
 async fn evaluation_service(
-    db: &DatabaseConnection,
-    token_rx: Receiver<u32>,
-    martix_tx: Sender<(u32, u32)>,
+    dbs: &[DatabaseConnection],
+    limiter: Option<Arc<Limiter>>,
+    martix_tx: Sender<Sample>,
     config: Config,
 ) -> Result<()> {
-    run_service(db, token_rx, martix_tx, config.concurrent, move |txn| {
-        Box::pin(async move {
-            let consumer_id = rand_i64(1, config.consumer_count as i64);
-            let commodity_id = rand_i64(1, config.commodity_count as i64);
-            commodity::Entity::find_by_id(commodity_id)
-                .one(txn)
-                .await?
-                .expect("Can't find the commodity");
-            consumer::Entity::find_by_id(consumer_id)
-                .one(txn)
-                .await?
-                .expect("Can't find the consumer");
-            evaluation::ActiveModel::rand_fake_new(consumer_id, commodity_id)
-                .insert(txn)
-                .await?;
-            Ok(1)
-        })
-    })
+    run_service(
+        dbs,
+        limiter,
+        martix_tx,
+        config,
+        config.evaluation,
+        move |txn| {
+            Box::pin(write_evaluation(
+                txn,
+                rand_pick(config.consumer_ids),
+                rand_pick(config.commodity_ids),
+            ))
+        },
+    )
     .await?;
     Ok(())
 }
 
 async fn change_price_service(
-    db: &DatabaseConnection,
-    token_rx: Receiver<u32>,
-    martix_tx: Sender<(u32, u32)>,
+    dbs: &[DatabaseConnection],
+    limiter: Option<Arc<Limiter>>,
+    martix_tx: Sender<Sample>,
     config: Config,
 ) -> Result<()> {
-    run_service(db, token_rx, martix_tx, config.concurrent, move |txn| {
-        Box::pin(async move {
-            let commodity_id = rand_i64(1, config.commodity_count as i64);
-            let mut commodity: commodity::ActiveModel = commodity::Entity::find_by_id(commodity_id)
-                .one(txn)
-                .await?
-                .expect("Can't find the commodity")
-                .into();
-            commodity.price = Set(rand_i64(1, 1000));
-            commodity.updated_at = Set(Local::now().naive_local());
-            commodity.update(txn).await?;
-            Ok(1)
-        })
-    })
+    run_service(
+        dbs,
+        limiter,
+        martix_tx,
+        config,
+        config.change_price,
+        move |txn| {
+            Box::pin(async move {
+                let commodity_id = rand_pick(config.commodity_ids);
+                let mut commodity: commodity::ActiveModel =
+                    commodity::Entity::find_by_id(commodity_id)
+                        .one(txn)
+                        .await?
+                        .expect("Can't find the commodity")
+                        .into();
+                commodity.price = Set(Price::rand(1, 1000));
+                commodity.updated_at = Set(Timestamp::now());
+                commodity.update(txn).await?;
+                Ok(1)
+            })
+        },
+    )
     .await?;
     Ok(())
 }
 
-async fn run_service<F>(
+/// Sells up to five of the commodity to the consumer, as long as the stock lasts.
+async fn place_order(
+    txn: &DatabaseTransaction,
+    consumer_id: Id,
+    commodity_id: Id,
+) -> std::result::Result<u32, DbErr> {
+    let commodity = match commodity::Entity::find_by_id(commodity_id).one(txn).await? {
+        Some(e) => e,
+        None => {
+            println!(
+                "[WARN] Can't find the commodity({}), retrying.",
+                commodity_id
+            );
+            return Ok(0);
+        }
+    };
+    let inventory = inventory::Entity::find_by_id(commodity_id)
+        .one(txn)
+        .await?
+        .expect("Can't find the inventory");
+    if inventory.inventory <= 0 {
+        return Ok(0);
+    }
+    consumer::Entity::find_by_id(consumer_id)
+        .one(txn)
+        .await?
+        .expect("Can't find the consumer");
+    let inventory_number = inventory.inventory;
+    let mut sold_number = rand_i64(1, 5);
+    if inventory_number < sold_number {
+        sold_number = inventory_number;
+    }
+    let mut inventory_active: inventory::ActiveModel = inventory.into();
+    inventory_active.updated_at = Set(Timestamp::now());
+    inventory_active.inventory = Set(inventory_number - sold_number);
+    inventory_active.update(txn).await?;
+
+    let mut order_active = order::ActiveModel::new();
+    order_active.consumer_id = Set(consumer_id);
+    order_active.commodity_id = Set(commodity_id);
+    order_active.sold_uint_price = Set(commodity.price);
+    order_active.currency = Set(commodity.currency);
+    order_active.sold_number = Set(sold_number);
+    order_active.insert(txn).await?;
+    Ok(2)
+}
+
+async fn write_evaluation(
+    txn: &DatabaseTransaction,
+    consumer_id: Id,
+    commodity_id: Id,
+) -> std::result::Result<u32, DbErr> {
+    commodity::Entity::find_by_id(commodity_id)
+        .one(txn)
+        .await?
+        .expect("Can't find the commodity");
+    consumer::Entity::find_by_id(consumer_id)
+        .one(txn)
+        .await?
+        .expect("Can't find the consumer");
+    evaluation::ActiveModel::rand_fake_new(consumer_id, commodity_id)
+        .insert(txn)
+        .await?;
+    Ok(1)
+}
+
+/// Runs the shoppers of `session`, each logged in as one consumer and
+/// pausing for a think time after every step.
+async fn session_service(
+    dbs: &[DatabaseConnection],
+    martix_tx: Sender<Sample>,
+    config: Config,
+    session: SessionConfig,
+) -> Result<()> {
+    let mut join_handle_vec = Vec::new();
+    for user in 0..session.users as usize {
+        let (endpoint, db) = pick_endpoint(dbs, config.balance);
+        let martix_tx = martix_tx.clone();
+        let consumer_id = config.consumer_ids[user % config.consumer_ids.len()];
+        let handler = tokio::spawn(async move {
+            'session: while !STOPPED.load(Ordering::Relaxed) {
+                session_step((endpoint, &db), &martix_tx, move |txn| {
+                    Box::pin(async move {
+                        consumer::Entity::find_by_id(consumer_id)
+                            .one(txn)
+                            .await?
+                            .expect("Can't find the consumer");
+                        Ok(0)
+                    })
+                })
+                .await?;
+                if !think(session).await {
+                    break;
+                }
+                let mut commodity_id = rand_pick(config.commodity_ids);
+                for _ in 0..session.browse_count.sample() {
+                    commodity_id = rand_pick(config.commodity_ids);
+                    session_step((endpoint, &db), &martix_tx, move |txn| {
+                        Box::pin(async move {
+                            commodity::Entity::find_by_id(commodity_id).one(txn).await?;
+                            inventory::Entity::find_by_id(commodity_id).one(txn).await?;
+                            Ok(0)
+                        })
+                    })
+                    .await?;
+                    if !think(session).await {
+                        break 'session;
+                    }
+                }
+                // the shopper settles on what it looked at last
+                if rand_f64() < session.evaluation_probability {
+                    session_step((endpoint, &db), &martix_tx, move |txn| {
+                        Box::pin(write_evaluation(txn, consumer_id, commodity_id))
+                    })
+                    .await?;
+                    if !think(session).await {
+                        break;
+                    }
+                }
+                session_step((endpoint, &db), &martix_tx, move |txn| {
+                    Box::pin(place_order(txn, consumer_id, commodity_id))
+                })
+                .await?;
+                if !think(session).await {
+                    break;
+                }
+            }
+            Ok::<_, DbErr>(())
+        });
+        join_handle_vec.push(handler);
+    }
+    let join_result = join_all(join_handle_vec).await;
+    for handle in join_result {
+        handle??;
+    }
+    Ok(())
+}
+
+/// Hands out the endpoint of a new worker, with its index.
+fn pick_endpoint(dbs: &[DatabaseConnection], balance: Balance) -> (usize, DatabaseConnection) {
+    let endpoint = match balance {
+        Balance::RoundRobin => NEXT_ENDPOINT.fetch_add(1, Ordering::Relaxed) % dbs.len(),
+        Balance::Random => rand_i64(0, dbs.len() as i64 - 1) as usize,
+    };
+    (endpoint, dbs[endpoint].clone())
+}
+
+/// Runs one step of a session until it doesn't conflict or Ctrl-C was pressed.
+async fn session_step<F>(
+    (endpoint, db): (usize, &DatabaseConnection),
+    martix_tx: &Sender<Sample>,
+    callback: F,
+) -> std::result::Result<(), DbErr>
+where
+    F: for<'c> Fn(
+            &'c DatabaseTransaction,
+        )
+            -> Pin<Box<dyn Future<Output = std::result::Result<u32, DbErr>> + Send + 'c>>
+        + Send
+        + Sync
+        + Copy
+        + 'static,
+{
+    while !STOPPED.load(Ordering::Relaxed) {
+        let now = Instant::now();
+        match timed_transaction(db, callback).await {
+            Ok((changed_row, pool_wait)) => {
+                committed(endpoint);
+                let _ = martix_tx.send(Sample::new(endpoint, changed_row, now, pool_wait, None));
+                break;
+            }
+            Err(err) if dialect::dialect().is_retryable(&err) => {
+                RETRIES.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(err) if lost_connection(endpoint, &err, now) => sleep(RECONNECT_BACKOFF).await,
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(())
+}
+
+/// Pauses for a think time, false once Ctrl-C was pressed.
+async fn think(session: SessionConfig) -> bool {
+    tokio::select! {
+        _ = sleep(session.think_time.sample()) => !STOPPED.load(Ordering::Relaxed),
+        _ = stopped() => false,
+    }
+}
+
+/// One finished transaction as reported to `martix_service`, in milliseconds.
+struct Sample {
+    /// The index of the database URL it ran against.
+    endpoint: usize,
+    changed_row: u32,
+    /// Waiting for a pooled connection and beginning the transaction.
+    pool_wait: u32,
+    /// The rest of the transaction.
+    execute_time: u32,
+    /// Since the intended start time of an open-loop transaction.
+    response_time: Option<u32>,
+}
+
+impl Sample {
+    fn new(
+        endpoint: usize,
+        changed_row: u32,
+        started: Instant,
+        pool_wait: Duration,
+        intended: Option<Instant>,
+    ) -> Self {
+        let elapsed = started.elapsed();
+        Sample {
+            endpoint,
+            changed_row,
+            pool_wait: pool_wait.as_millis() as u32,
+            execute_time: elapsed.saturating_sub(pool_wait).as_millis() as u32,
+            response_time: intended.map(|intended| intended.elapsed().as_millis() as u32),
+        }
+    }
+}
+
+/// The transactions of one step of a schedule or of one endpoint.
+#[derive(Debug, Default)]
+struct TxnStats {
+    txns: u64,
+    rows: u64,
+    retries: u64,
+    execute_times: Vec<u32>,
+    pool_waits: Vec<u32>,
+    response_times: Vec<u32>,
+}
+
+impl TxnStats {
+    fn record(&mut self, sample: &Sample) {
+        self.txns += 1;
+        self.rows += sample.changed_row as u64;
+        self.execute_times.push(sample.execute_time);
+        self.pool_waits.push(sample.pool_wait);
+        self.response_times.extend(sample.response_time);
+    }
+}
+
+/// Runs `callback` in a transaction like `TransactionTrait::transaction`, also
+/// returning how long getting a connection from the pool and beginning took.
+async fn timed_transaction<F>(
     db: &DatabaseConnection,
-    token_rx: Receiver<u32>,
-    martix_tx: Sender<(u32, u32)>,
-    concurrent: u32,
+    callback: F,
+) -> std::result::Result<(u32, Duration), DbErr>
+where
+    F: for<'c> FnOnce(
+            &'c DatabaseTransaction,
+        )
+            -> Pin<Box<dyn Future<Output = std::result::Result<u32, DbErr>> + Send + 'c>>
+        + Send,
+{
+    let now = Instant::now();
+    let txn = db.begin().await?;
+    let pool_wait = now.elapsed();
+    match callback(&txn).await {
+        Ok(changed_row) => {
+            txn.commit().await?;
+            Ok((changed_row, pool_wait))
+        }
+        Err(err) => {
+            // the error of the transaction matters more than the one of the rollback
+            let _ = txn.rollback().await;
+            Err(err)
+        }
+    }
+}
+
+async fn run_service<F>(
+    dbs: &[DatabaseConnection],
+    limiter: Option<Arc<Limiter>>,
+    martix_tx: Sender<Sample>,
+    config: Config,
+    service: ServiceConfig,
     callback: F,
 ) -> Result<()>
 where
@@ -224,34 +941,66 @@ where
         + Copy
         + 'static,
 {
+    let dialect = dialect::dialect();
     let mut join_handle_vec = Vec::new();
-    for i in 0..concurrent {
-        let db = db.clone();
-        let token_rx = token_rx.clone();
+    for _ in 0..service.concurrent {
+        let (endpoint, db) = pick_endpoint(dbs, config.balance);
+        let limiter = limiter.clone();
         let martix_tx = martix_tx.clone();
         let handler = tokio::spawn(async move {
-            while let Ok(mut token) = token_rx.recv_async().await {
-                while token > 0 {
-                    let now = Instant::now();
-                    let result = db
-                        .transaction::<_, u32, DbErr>(callback)
-                        .await
-                        .map_err(|err| match err {
-                            sea_orm::TransactionError::Connection(err) => err,
-                            sea_orm::TransactionError::Transaction(err) => err,
-                        });
-                    let elapsed = now.elapsed().as_millis() as u32;
-                    match result {
-                        Ok(changed_row) => {
-                            if token >= changed_row {
-                                token -= changed_row;
-                            } else {
-                                token = 0;
+            // the start time a retried transaction was intended to run at
+            let mut retried = None;
+            while !STOPPED.load(Ordering::Relaxed) {
+                // without a limiter the workers run closed-loop
+                let intended = match (limiter.as_deref(), config.rate_unit) {
+                    (Some(Limiter::Bucket(bucket)), RateUnit::Txn) => {
+                        tokio::select! {
+                            _ = bucket.take(1.0) => None,
+                            _ = stopped() => break,
+                        }
+                    }
+                    (Some(Limiter::OpenLoop(arrivals)), _) => match retried.take() {
+                        Some(intended) => Some(intended),
+                        None => tokio::select! {
+                            intended = arrivals.next() => Some(intended),
+                            _ = stopped() => break,
+                        },
+                    },
+                    _ => None,
+                };
+                let now = Instant::now();
+                match timed_transaction(&db, callback).await {
+                    Ok((changed_row, pool_wait)) => {
+                        committed(endpoint);
+                        let _ = martix_tx.send(Sample::new(
+                            endpoint,
+                            changed_row,
+                            now,
+                            pool_wait,
+                            intended,
+                        ));
+                        if let (Some(Limiter::Bucket(bucket)), RateUnit::Row) =
+                            (limiter.as_deref(), config.rate_unit)
+                        {
+                            // pay for the rows afterwards, they aren't known before
+                            tokio::select! {
+                                _ = bucket.take(changed_row.max(1) as f64) => {}
+                                _ = stopped() => break,
                             }
-                            martix_tx.send((changed_row, elapsed));
                         }
-                        Err(err) => return Err(err),
                     }
+                    Err(err) if dialect.is_retryable(&err) => {
+                        RETRIES.fetch_add(1, Ordering::Relaxed);
+                        retried = intended;
+                    }
+                    Err(err) if lost_connection(endpoint, &err, now) => {
+                        retried = intended;
+                        tokio::select! {
+                            _ = sleep(RECONNECT_BACKOFF) => {}
+                            _ = stopped() => break,
+                        }
+                    }
+                    Err(err) => return Err(err),
                 }
             }
             Ok(())
@@ -265,29 +1014,124 @@ where
     Ok(())
 }
 
-async fn martix_service(martix_rx: Receiver<(u32, u32)>) {
+/// Reports the latency and throughput every second, broken down by endpoint
+/// if there are several, and, when following a schedule, per step once the
+/// run is over.
+async fn martix_service(
+    martix_rx: Receiver<Sample>,
+    name: &str,
+    retries: &AtomicU64,
+    schedule: Option<(&LoadSchedule, Instant)>,
+    endpoints: usize,
+) {
     let mut now = Instant::now();
     let mut execute_time_vec = vec![];
+    let mut response_time_vec = vec![];
+    let mut pool_wait_vec = vec![];
     let mut changed_row_per_sec = 0;
-    while let Ok((changed_row, execute_time)) = martix_rx.recv_async().await {
-        execute_time_vec.push(execute_time);
-        changed_row_per_sec += changed_row;
+    let mut steps: Vec<TxnStats> = Vec::new();
+    let mut current_step = 0;
+    let mut endpoint_stats: Vec<TxnStats> = Vec::new();
+    while let Ok(sample) = martix_rx.recv_async().await {
+        execute_time_vec.push(sample.execute_time);
+        pool_wait_vec.push(sample.pool_wait);
+        response_time_vec.extend(sample.response_time);
+        changed_row_per_sec += sample.changed_row;
+        if endpoints > 1 {
+            endpoint_stats.resize_with(endpoints, TxnStats::default);
+            endpoint_stats[sample.endpoint].record(&sample);
+        }
+        if let Some((schedule, started)) = schedule {
+            let elapsed = started.elapsed();
+            current_step = schedule.step_at(elapsed);
+            if steps.len() <= current_step {
+                steps.resize_with(current_step + 1, TxnStats::default);
+            }
+            steps[current_step].record(&sample);
+        }
         if now.elapsed() > Duration::from_secs(1) {
-            execute_time_vec.sort();
-            let max = execute_time_vec[execute_time_vec.len() - 1];
-            let p50 = execute_time_vec[(execute_time_vec.len() as f32 * 0.5) as usize];
-            let p80 = execute_time_vec[(execute_time_vec.len() as f32 * 0.8) as usize];
-            let p95 = execute_time_vec[(execute_time_vec.len() as f32 * 0.95) as usize];
-            let p99 = execute_time_vec[(execute_time_vec.len() as f32 * 0.99) as usize];
-            let p999 = execute_time_vec[(execute_time_vec.len() as f32 * 0.999) as usize];
-
+            let retry_count = retries.swap(0, Ordering::Relaxed);
+            if let Some(stats) = steps.get_mut(current_step) {
+                stats.retries += retry_count;
+            }
             println!(
-                "{} Txn Execute Time(P50:{}ms, P80:{}ms, P95:{}ms, P99:{}ms, P999:{}ms, Max:{}ms), {} Row/s",
-                Local::now(), p50, p80, p95, p99, p999, max, changed_row_per_sec
+                "{} {} {}{}{}, {} Row/s, {} Retry/s",
+                Local::now(),
+                name,
+                latency("", "Execute", &mut execute_time_vec),
+                latency(", ", "Pool Wait", &mut pool_wait_vec),
+                latency(", ", "Response", &mut response_time_vec),
+                changed_row_per_sec,
+                retry_count
             );
+            for (endpoint, stats) in endpoint_stats.iter_mut().enumerate() {
+                println!(
+                    "{} {} Endpoint #{} {} Txn/s, {}{}{}, {} Row/s",
+                    Local::now(),
+                    name,
+                    endpoint + 1,
+                    stats.txns,
+                    latency("", "Execute", &mut stats.execute_times),
+                    latency(", ", "Pool Wait", &mut stats.pool_waits),
+                    latency(", ", "Response", &mut stats.response_times),
+                    stats.rows
+                );
+            }
+            endpoint_stats.clear();
             now = Instant::now();
             changed_row_per_sec = 0;
             execute_time_vec.clear();
+            pool_wait_vec.clear();
+            response_time_vec.clear();
+        }
+    }
+    if let Some((schedule, started)) = schedule {
+        println!("Summary per step:");
+        let stopped = started.elapsed();
+        for (step, stats) in steps.iter_mut().enumerate() {
+            // the rates are over the scheduled time of the step, cut short
+            // where the run stopped
+            let start = schedule.step_start(step);
+            let end = schedule
+                .step_end(step)
+                .map_or(stopped, |end| end.min(stopped));
+            let seconds = end.saturating_sub(start).as_secs_f64();
+            if seconds == 0.0 {
+                continue;
+            }
+            println!(
+                "Step {} ({}): {} Txn, {:.1} Txn/s, {:.1} Row/s, {} Retry, {}{}{}",
+                step + 1,
+                schedule.describe_step(step),
+                stats.txns,
+                stats.txns as f64 / seconds,
+                stats.rows as f64 / seconds,
+                stats.retries,
+                latency("", "Execute", &mut stats.execute_times),
+                latency(", ", "Pool Wait", &mut stats.pool_waits),
+                latency(", ", "Response", &mut stats.response_times),
+            );
         }
     }
 }
+
+/// The P50, P80, P95, P99, P999 and maximum of `times` after `separator`,
+/// nothing if there are none.
+fn latency(separator: &str, name: &str, times: &mut [u32]) -> String {
+    if times.is_empty() {
+        return String::new();
+    }
+    times.sort_unstable();
+    let at = |quantile: f32| times[(times.len() as f32 * quantile) as usize];
+    format!(
+        "{}{} Time(P50:{}ms, P80:{}ms, P95:{}ms, P99:{}ms, P999:{}ms, Max:{}ms)",
+        separator,
+        name,
+        at(0.5),
+        at(0.8),
+        at(0.95),
+        at(0.99),
+        at(0.999),
+        times[times.len() - 1]
+    )
+}
@@ -0,0 +1,180 @@
+use anyhow::{bail, Context, Result};
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// `FROM:TO:SECONDS`, a linear ramp of the rate.
+#[derive(Debug, Clone, Copy)]
+pub struct Ramp {
+    from: f64,
+    to: f64,
+    seconds: f64,
+}
+
+impl FromStr for Ramp {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match parse_numbers(s)?[..] {
+            [from, to, seconds] if seconds > 0.0 => Ok(Ramp { from, to, seconds }),
+            _ => Err("expected FROM:TO:SECONDS with SECONDS > 0".to_owned()),
+        }
+    }
+}
+
+/// `START:INCREMENT:SECONDS`, a rate raised by a fixed amount every interval.
+#[derive(Debug, Clone, Copy)]
+pub struct Step {
+    start: f64,
+    increment: f64,
+    seconds: f64,
+}
+
+impl FromStr for Step {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match parse_numbers(s)?[..] {
+            [start, increment, seconds] if seconds > 0.0 => Ok(Step {
+                start,
+                increment,
+                seconds,
+            }),
+            _ => Err("expected START:INCREMENT:SECONDS with SECONDS > 0".to_owned()),
+        }
+    }
+}
+
+fn parse_numbers(s: &str) -> Result<Vec<f64>, String> {
+    s.split(':')
+        .map(|number| {
+            number
+                .trim()
+                .parse::<f64>()
+                .ok()
+                .filter(|number| *number >= 0.0)
+                .ok_or_else(|| format!("invalid number {:?}", number))
+        })
+        .collect()
+}
+
+/// Slices a ramp is summarized in.
+const RAMP_SLICES: usize = 10;
+
+/// How the target rate of `run` changes over time. Every schedule holds its
+/// last rate until the run is stopped.
+#[derive(Debug)]
+pub enum LoadSchedule {
+    Ramp(Ramp),
+    Step(Step),
+    /// `(seconds, rate)` pairs, starting at 0 and sorted by time.
+    File(Vec<(f64, f64)>),
+}
+
+impl LoadSchedule {
+    /// Reads a schedule file with one `SECONDS,RATE` pair per line, the rate
+    /// holding from that second on. Empty lines and `#` comments are skipped.
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        let mut points = Vec::new();
+        for (i, line) in content.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let numbers = parse_numbers(&line.replace(',', ":"))
+                .map_err(|err| anyhow::anyhow!("{}:{}: {}", path.display(), i + 1, err))?;
+            let point = match numbers[..] {
+                [seconds, rate] => (seconds, rate),
+                _ => bail!("{}:{}: expected SECONDS,RATE", path.display(), i + 1),
+            };
+            if points
+                .last()
+                .map_or(point.0 != 0.0, |last: &(f64, f64)| point.0 <= last.0)
+            {
+                bail!(
+                    "{}:{}: the times must start at 0 and increase",
+                    path.display(),
+                    i + 1
+                );
+            }
+            points.push(point);
+        }
+        if points.is_empty() {
+            bail!("{} holds no schedule", path.display());
+        }
+        Ok(LoadSchedule::File(points))
+    }
+
+    /// The target rate `elapsed` into the run.
+    pub fn rate_at(&self, elapsed: Duration) -> f64 {
+        let seconds = elapsed.as_secs_f64();
+        match self {
+            LoadSchedule::Ramp(ramp) => {
+                let progress = (seconds / ramp.seconds).min(1.0);
+                ramp.from + (ramp.to - ramp.from) * progress
+            }
+            LoadSchedule::Step(step) => {
+                step.start + step.increment * (seconds / step.seconds).floor()
+            }
+            LoadSchedule::File(points) => points[self.step_at(elapsed)].1,
+        }
+    }
+
+    /// The step of the summary `elapsed` falls in. A ramp is cut into
+    /// `RAMP_SLICES` steps followed by the one holding its final rate.
+    pub fn step_at(&self, elapsed: Duration) -> usize {
+        let seconds = elapsed.as_secs_f64();
+        match self {
+            LoadSchedule::Ramp(ramp) => {
+                ((seconds / ramp.seconds * RAMP_SLICES as f64) as usize).min(RAMP_SLICES)
+            }
+            LoadSchedule::Step(step) => (seconds / step.seconds) as usize,
+            LoadSchedule::File(points) => points
+                .iter()
+                .rposition(|(from, _)| seconds >= *from)
+                .unwrap_or(0),
+        }
+    }
+
+    /// When `step` begins, counted from the start of the run.
+    pub fn step_start(&self, step: usize) -> Duration {
+        Duration::from_secs_f64(match self {
+            LoadSchedule::Ramp(ramp) => {
+                ramp.seconds * step.min(RAMP_SLICES) as f64 / RAMP_SLICES as f64
+            }
+            LoadSchedule::Step(step_schedule) => step_schedule.seconds * step as f64,
+            // the first point also covers the time before it
+            LoadSchedule::File(_) if step == 0 => 0.0,
+            LoadSchedule::File(points) => points[step].0,
+        })
+    }
+
+    /// When `step` ends, `None` for the last one, which holds until the run stops.
+    pub fn step_end(&self, step: usize) -> Option<Duration> {
+        match self {
+            LoadSchedule::Ramp(_) if step >= RAMP_SLICES => None,
+            LoadSchedule::File(points) if step + 1 >= points.len() => None,
+            _ => Some(self.step_start(step + 1)),
+        }
+    }
+
+    /// Describes the target rate of `step`.
+    pub fn describe_step(&self, step: usize) -> String {
+        match self {
+            LoadSchedule::Ramp(ramp) if step < RAMP_SLICES => {
+                let rate = |slice: usize| {
+                    ramp.from + (ramp.to - ramp.from) * slice as f64 / RAMP_SLICES as f64
+                };
+                format!("{:.1} -> {:.1}/s", rate(step), rate(step + 1))
+            }
+            LoadSchedule::Ramp(ramp) => format!("{:.1}/s", ramp.to),
+            LoadSchedule::Step(step_schedule) => format!(
+                "{:.1}/s",
+                step_schedule.start + step_schedule.increment * step as f64
+            ),
+            LoadSchedule::File(points) => format!("{:.1}/s", points[step].1),
+        }
+    }
+}
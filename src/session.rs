@@ -0,0 +1,120 @@
+use crate::rand::{rand_f64, rand_i64};
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// How long a shopper pauses between the steps of a session.
+#[derive(Debug, Clone, Copy)]
+pub enum ThinkTime {
+    /// `none`
+    None,
+    /// `fixed:MS`
+    Fixed(u64),
+    /// `uniform:MIN:MAX`, in milliseconds
+    Uniform(u64, u64),
+    /// `exp:MEAN`, exponentially distributed around a mean in milliseconds
+    Exponential(u64),
+}
+
+impl FromStr for ThinkTime {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split(':');
+        let kind = parts.next().unwrap_or_default();
+        let millis = parts
+            .map(|millis| {
+                millis
+                    .trim()
+                    .parse::<u64>()
+                    .map_err(|_| format!("invalid milliseconds {:?}", millis))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        match (kind, &millis[..]) {
+            ("none", []) => Ok(ThinkTime::None),
+            ("fixed", [millis]) => Ok(ThinkTime::Fixed(*millis)),
+            ("uniform", [min, max]) if min <= max => Ok(ThinkTime::Uniform(*min, *max)),
+            ("exp", [mean]) => Ok(ThinkTime::Exponential(*mean)),
+            _ => Err("expected none, fixed:MS, uniform:MIN:MAX or exp:MEAN".to_owned()),
+        }
+    }
+}
+
+impl fmt::Display for ThinkTime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ThinkTime::None => write!(f, "none"),
+            ThinkTime::Fixed(millis) => write!(f, "fixed:{}", millis),
+            ThinkTime::Uniform(min, max) => write!(f, "uniform:{}:{}", min, max),
+            ThinkTime::Exponential(mean) => write!(f, "exp:{}", mean),
+        }
+    }
+}
+
+impl ThinkTime {
+    /// Draws the length of one pause.
+    pub fn sample(&self) -> Duration {
+        match *self {
+            ThinkTime::None => Duration::ZERO,
+            ThinkTime::Fixed(millis) => Duration::from_millis(millis),
+            ThinkTime::Uniform(min, max) => {
+                Duration::from_millis(rand_i64(min as i64, max as i64) as u64)
+            }
+            ThinkTime::Exponential(mean) => {
+                Duration::from_secs_f64(-(1.0 - rand_f64()).ln() * mean as f64 / 1000.0)
+            }
+        }
+    }
+}
+
+/// `MIN:MAX`, how many commodities a shopper looks at before ordering.
+#[derive(Debug, Clone, Copy)]
+pub struct BrowseCount {
+    min: u32,
+    max: u32,
+}
+
+impl FromStr for BrowseCount {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let counts = s
+            .split(':')
+            .map(|count| count.trim().parse::<u32>())
+            .collect::<Result<Vec<_>, _>>();
+        match counts.as_deref() {
+            Ok([min, max]) if 1 <= *min && min <= max => Ok(BrowseCount {
+                min: *min,
+                max: *max,
+            }),
+            _ => Err("expected MIN:MAX with 1 <= MIN <= MAX".to_owned()),
+        }
+    }
+}
+
+impl Default for BrowseCount {
+    fn default() -> Self {
+        BrowseCount { min: 1, max: 5 }
+    }
+}
+
+impl fmt::Display for BrowseCount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.min, self.max)
+    }
+}
+
+impl BrowseCount {
+    pub fn sample(&self) -> u32 {
+        rand_i64(self.min as i64, self.max as i64) as u32
+    }
+}
+
+/// The virtual users of `run --sessions`.
+#[derive(Debug, Clone, Copy)]
+pub struct SessionConfig {
+    pub users: u32,
+    pub think_time: ThinkTime,
+    pub browse_count: BrowseCount,
+    pub evaluation_probability: f64,
+}